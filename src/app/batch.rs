@@ -0,0 +1,84 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use futures::{Stream, StreamExt};
+
+use common::format::{self, OutputFormat};
+use common::lookup::{DomainLookupOptions, LookupHandler, LookupResult};
+use common::projection::Projection;
+
+use crate::input;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchOrder {
+    Input,
+    Completion,
+}
+
+impl BatchOrder {
+    pub fn from_str(order: &str) -> Option<BatchOrder> {
+        match order {
+            "input" => Some(BatchOrder::Input),
+            "completion" => Some(BatchOrder::Completion),
+            _ => None,
+        }
+    }
+}
+
+pub async fn execute_batch(
+    handler: LookupHandler,
+    source: &str,
+    options: DomainLookupOptions,
+    parallelism: usize,
+    order: BatchOrder,
+    output_format: OutputFormat,
+    projection: &Projection,
+) -> io::Result<()> {
+    let reader: Box<dyn BufRead + Send> = if source == "-" {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(source)?))
+    };
+
+    let mut error = None;
+    let handler = &handler;
+    let options = &options;
+    let lookups = input::read_lines(reader, &mut error)
+        .map(|query| async move { handler.lookup(query, options).await });
+
+    if let Some(header) = output_format.header(projection) {
+        print!("{}", header);
+    }
+    match order {
+        BatchOrder::Input => {
+            print_results(lookups.buffered(parallelism), output_format, projection).await
+        }
+        BatchOrder::Completion => {
+            print_results(
+                lookups.buffer_unordered(parallelism),
+                output_format,
+                projection,
+            )
+            .await
+        }
+    }
+
+    match error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+async fn print_results(
+    results: impl Stream<Item = LookupResult>,
+    output_format: OutputFormat,
+    projection: &Projection,
+) {
+    futures::pin_mut!(results);
+    while let Some(result) = results.next().await {
+        print!(
+            "{}",
+            format::format_item(&result, output_format, projection)
+        );
+    }
+}
@@ -1,4 +1,10 @@
+use cidr::AnyIpCidr;
 use clap::{App, Arg};
+use common::cors;
+use common::format::OutputFormat;
+use common::listener::Listener;
+use common::logging::{LogFormat, LogLevel};
+use std::net::IpAddr;
 
 pub fn create_cli_app() -> App<'static, 'static> {
     App::new("net-lookup")
@@ -28,16 +34,194 @@ pub fn create_cli_app() -> App<'static, 'static> {
         .arg(
             Arg::with_name("host")
                 .long("host")
-                .help("Host to bind service to.")
-                .default_value("0.0.0.0")
+                .help("Host to bind service to, 0.0.0.0 by default.")
+                .conflicts_with("listen")
                 .takes_value(true),
         )
         .arg(
             Arg::with_name("port")
                 .short("-p")
                 .long("port")
-                .help("Port to bind service to.")
-                .default_value("8080")
+                .help("Port to bind service to, 8080 by default.")
+                .conflicts_with("listen")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("listen")
+                .long("listen")
+                .value_name("ADDR")
+                .help("Address to serve on, tcp://<ip>:<port> or unix://<path>. Used instead of --host and --port.")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|v| Listener::parse(&v).map(|_| ()))
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("unix-socket-mode")
+                .long("unix-socket-mode")
+                .value_name("MODE")
+                .help("Octal file permissions of unix sockets.")
+                .default_value("660")
+                .validator(|v| {
+                    u32::from_str_radix(&v, 8)
+                        .map(|_| ())
+                        .map_err(|_| format!("'{}' is not an octal file mode", v))
+                })
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("require-source")
+                .long("require-source")
+                .value_name("NAME")
+                .help("Report not ready while this data source is disabled (asn, ip2asn, maxmind, public-suffix-list or mmdb-NAME).")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("admin-routes")
+                .long("admin-routes")
+                .help("Enables POST /admin/reload. It requires a key marked admin with --api-keys, and a unix socket otherwise."),
+        )
+        .arg(
+            Arg::with_name("legacy-routes")
+                .long("legacy-routes")
+                .help("Also serves lookups from the legacy /<ip-address-or-domain> route."),
+        )
+        .arg(
+            Arg::with_name("trusted-proxy")
+                .long("trusted-proxy")
+                .value_name("CIDR")
+                .help("Proxy network whose Forwarded, X-Forwarded-For and X-Real-IP headers are trusted.")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|v| {
+                    v.parse::<AnyIpCidr>()
+                        .map(|_| ())
+                        .map_err(|_| format!("'{}' is not a valid cidr", v))
+                })
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tls-cert")
+                .long("tls-cert")
+                .value_name("FILE")
+                .help("PEM certificate chain to serve the daemon over https.")
+                .requires("tls-key")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tls-key")
+                .long("tls-key")
+                .value_name("FILE")
+                .help("PEM private key for --tls-cert.")
+                .requires("tls-cert")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tls-client-ca")
+                .long("tls-client-ca")
+                .value_name("FILE")
+                .help("PEM CA bundle, clients must present a certificate signed by one of these CAs.")
+                .requires("tls-cert")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("api-keys")
+                .long("api-keys")
+                .value_name("FILE")
+                .help("File of '<key> [name] [requests-per-second] [admin]' lines, requests must present one of the keys.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("key-rate-limit")
+                .long("key-rate-limit")
+                .value_name("RATE")
+                .help("Requests per second allowed for each api key.")
+                .requires("api-keys")
+                .validator(validate_rate)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ip-rate-limit")
+                .long("ip-rate-limit")
+                .value_name("RATE")
+                .help("Requests per second allowed for each client ip address.")
+                .validator(validate_rate)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rate-limit-burst")
+                .long("rate-limit-burst")
+                .value_name("REQUESTS")
+                .help("Requests a client may make at once before the rate limits apply (defaults to one second worth).")
+                .validator(validate_rate)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cors-origin")
+                .long("cors-origin")
+                .value_name("ORIGIN")
+                .help("Origin allowed to call the service from a browser ('*' allows any origin).")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cors-methods")
+                .long("cors-methods")
+                .value_name("METHODS")
+                .help("Comma separated methods allowed for cross origin requests.")
+                .default_value("GET, POST")
+                .validator(|v| cors::parse_methods(&v).map(|_| ()))
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cors-max-age")
+                .long("cors-max-age")
+                .value_name("SECONDS")
+                .help("How long browsers may cache preflight responses.")
+                .default_value("600")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cache-max-age")
+                .long("cache-max-age")
+                .value_name("SECONDS")
+                .help("Upper bound of the Cache-Control max-age of lookup responses.")
+                .default_value("3600")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("shutdown-delay")
+                .long("shutdown-delay")
+                .value_name("SECONDS")
+                .help("How long the daemon reports not ready after SIGTERM or SIGINT before it stops accepting connections.")
+                .default_value("5")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("shutdown-timeout")
+                .long("shutdown-timeout")
+                .value_name("SECONDS")
+                .help("How long the daemon waits for in-flight requests to finish when shutting down.")
+                .default_value("30")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("reload-interval")
+                .long("reload-interval")
+                .value_name("SECONDS")
+                .help("How often the daemon checks data files for changes and reloads them (0 disables).")
+                .default_value("60")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-batch-size")
+                .long("max-batch-size")
+                .help("Maximum number of queries accepted by a single batch request.")
+                .default_value("1000")
+                .validator(validate_positive)
                 .takes_value(true),
         )
         .arg(
@@ -59,6 +243,28 @@ pub fn create_cli_app() -> App<'static, 'static> {
                 .help("Specify maxmind city database file.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("maxmind-database")
+                .long("maxmind-database")
+                .value_name("FILE")
+                .help("Specify an additional maxmind database file (ASN, Country, ISP, Connection-Type, Domain or Anonymous-IP).")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mmdb")
+                .long("mmdb")
+                .value_name("NAME=FILE")
+                .help("Specify a custom mmdb file whose records are added to results under 'extra.NAME'.")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|v| match v.split_once('=') {
+                    Some((name, path)) if !name.is_empty() && !path.is_empty() => Ok(()),
+                    _ => Err("expected NAME=FILE".to_owned()),
+                })
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("asn-database")
                 .long("asn-database")
@@ -71,10 +277,143 @@ pub fn create_cli_app() -> App<'static, 'static> {
                 .help("Specify ip2asn database file.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("public-suffix-list")
+                .long("public-suffix-list")
+                .help("Specify public suffix list file.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("format")
+                .short("-f")
+                .long("format")
+                .help("Output format for lookup results.")
+                .possible_values(OutputFormat::names())
+                .default_value("json")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("fields")
+                .long("fields")
+                .value_name("FIELDS")
+                .help("Comma separated list of fields to include in results (e.g. ip,asn.id,geo.location).")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("full-geo")
+                .long("full-geo")
+                .help("Includes the complete geo record with names in all languages."),
+        )
+        .arg(
+            Arg::with_name("lang")
+                .long("lang")
+                .value_name("LANG")
+                .help("Resolves geo names to the given language(s), falling back to English.")
+                .conflicts_with("full-geo")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("info")
+                .long("info")
+                .help("Prints metadata and freshness of the loaded data files."),
+        )
+        .arg(
+            Arg::with_name("max-data-age")
+                .long("max-data-age")
+                .value_name("DAYS")
+                .help("Age in days after which data files are reported as stale.")
+                .default_value("30")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("distance")
+                .long("distance")
+                .value_names(&["IP", "IP"])
+                .help("Prints the great-circle distance in km between two ip addresses.")
+                .number_of_values(2)
+                .validator(|v| {
+                    v.parse::<IpAddr>()
+                        .map(|_| ())
+                        .map_err(|_| format!("'{}' is not a valid ip address", v))
+                }),
+        )
+        .arg(
+            Arg::with_name("detect-wildcard")
+                .long("detect-wildcard")
+                .help("Probes the parent zone of domain queries for wildcard records."),
+        )
+        .arg(
+            Arg::with_name("enumerate")
+                .long("enumerate")
+                .value_name("DOMAIN")
+                .help("Enumerates subdomains of the given domain using a wordlist.")
+                .requires("wordlist")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("wordlist")
+                .long("wordlist")
+                .value_name("FILE")
+                .help("Wordlist of subdomain labels used for enumeration.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("batch")
+                .long("batch")
+                .value_name("FILE")
+                .help("Runs a lookup for each line of the given file ('-' for stdin).")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("batch-order")
+                .long("batch-order")
+                .help("Order batch results are written in.")
+                .possible_values(&["input", "completion"])
+                .default_value("input")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("parallelism")
+                .long("parallelism")
+                .help("Maximum number of lookups to run concurrently.")
+                .default_value("32")
+                .validator(validate_positive)
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("verbose")
                 .short("-v")
                 .long("verbose")
                 .help("Enables verbose logging"),
         )
+        .arg(
+            Arg::with_name("log-level")
+                .long("log-level")
+                .help("Minimum level of log messages written to stderr.")
+                .possible_values(LogLevel::names())
+                .default_value("info")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("log-format")
+                .long("log-format")
+                .help("Format of log messages.")
+                .possible_values(LogFormat::names())
+                .default_value("text")
+                .takes_value(true),
+        )
+}
+
+fn validate_positive(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(count) if count >= 1 => Ok(()),
+        _ => Err(format!("'{}' is not a positive integer", value)),
+    }
+}
+
+fn validate_rate(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(()),
+        _ => Err(format!("'{}' is not a positive number", value)),
+    }
 }
@@ -1,20 +1,57 @@
-use clap::ArgMatches;
-use std::net::IpAddr;
+use cidr::AnyIpCidr;
+use clap::{ArgMatches, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
+use crate::batch::BatchOrder;
 use crate::cli;
+use crate::common::access::RateLimit;
+use crate::common::cors::{self, CorsOptions};
 use crate::common::files;
+use crate::common::format::OutputFormat;
+use crate::common::listener::Listener;
+use crate::common::logging::{LogFormat, LogLevel};
+use crate::common::projection::{GeoView, Projection};
+use crate::common::tls::TlsOptions;
 
 pub fn load_config() -> LookupConfig {
     let cli_app = cli::create_cli_app();
     let matches = cli_app.get_matches();
+    let host = get_string_value(&matches, "host")
+        .unwrap_or("0.0.0.0".to_owned())
+        .parse::<IpAddr>()
+        .unwrap();
+    let port = get_string_value(&matches, "port")
+        .and_then(|p| p.parse::<u16>().ok())
+        .unwrap_or(8080);
+    let format = get_string_value(&matches, "format")
+        .and_then(|f| OutputFormat::from_name(&f))
+        .unwrap_or(OutputFormat::Json);
+    let projection = Projection::new(
+        matches.value_of("fields"),
+        if matches.is_present("full-geo") {
+            GeoView::Full
+        } else if let Some(lang) = matches.value_of("lang") {
+            GeoView::localized(lang)
+        } else {
+            GeoView::Compact
+        },
+    );
+    if let Err(err) = format.check_fields(&projection) {
+        clap::Error::with_description(&err, ErrorKind::InvalidValue).exit();
+    }
     LookupConfig {
-        host: get_string_value(&matches, "host")
-            .unwrap_or("0.0.0.0".to_owned())
-            .parse::<IpAddr>()
-            .unwrap(),
-        port: get_value::<u16>(&matches, "port").unwrap_or(8080),
+        listeners: matches
+            .values_of("listen")
+            .map(|values| values.map(|v| Listener::parse(v).unwrap()).collect())
+            .unwrap_or_else(|| vec![Listener::Tcp(SocketAddr::new(host, port))]),
+        unix_socket_mode: matches
+            .value_of("unix-socket-mode")
+            .and_then(|m| u32::from_str_radix(m, 8).ok())
+            .unwrap_or(0o660),
+        max_batch_size: get_value::<usize>(&matches, "max-batch-size").unwrap_or(1000),
         resolver_host: get_string_value(&matches, "resolver-host")
             .map(|s| s.parse::<IpAddr>().unwrap()),
         resolver_port: get_value::<u16>(&matches, "resolver-port").unwrap_or(53),
@@ -23,6 +60,8 @@ pub fn load_config() -> LookupConfig {
             "maxmind-city-database",
             files::get_default_maxmind_path(),
         ),
+        maxmind_database_files: get_maxmind_database_files(&matches),
+        custom_mmdb_files: get_custom_mmdb_files(&matches),
         asn_database_file: get_file_path_or(
             &matches,
             "asn-database",
@@ -33,12 +72,125 @@ pub fn load_config() -> LookupConfig {
             "ip2asn-database",
             files::get_default_ip2asn_path(),
         ),
+        public_suffix_list_file: get_file_path_or(
+            &matches,
+            "public-suffix-list",
+            files::get_default_public_suffix_list_path(),
+        ),
         daemon: matches.is_present("daemon"),
+        legacy_routes: matches.is_present("legacy-routes"),
+        admin_routes: matches.is_present("admin-routes"),
+        required_sources: matches
+            .values_of("require-source")
+            .map(|values| values.map(|v| v.to_owned()).collect())
+            .unwrap_or_default(),
+        tls: get_string_value(&matches, "tls-cert").map(|cert| TlsOptions {
+            cert_file: PathBuf::from(cert),
+            key_file: PathBuf::from(matches.value_of("tls-key").unwrap()),
+            client_ca_file: get_string_value(&matches, "tls-client-ca").map(PathBuf::from),
+        }),
+        api_keys_file: get_string_value(&matches, "api-keys").map(PathBuf::from),
+        key_rate_limit: get_rate_limit(&matches, "key-rate-limit"),
+        ip_rate_limit: get_rate_limit(&matches, "ip-rate-limit"),
+        cors: matches.values_of("cors-origin").map(|origins| CorsOptions {
+            origins: origins.map(|o| o.to_owned()).collect(),
+            methods: cors::parse_methods(matches.value_of("cors-methods").unwrap()).unwrap(),
+            max_age: Duration::from_secs(get_value::<u64>(&matches, "cors-max-age").unwrap_or(600)),
+        }),
+        cache_max_age: Duration::from_secs(
+            get_value::<u64>(&matches, "cache-max-age").unwrap_or(3600),
+        ),
+        shutdown_delay: Duration::from_secs(
+            get_value::<u64>(&matches, "shutdown-delay").unwrap_or(5),
+        ),
+        shutdown_timeout: Duration::from_secs(
+            get_value::<u64>(&matches, "shutdown-timeout").unwrap_or(30),
+        ),
+        reload_interval: get_value::<u64>(&matches, "reload-interval")
+            .ok()
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs),
+        trusted_proxies: matches
+            .values_of("trusted-proxy")
+            .map(|values| values.map(|v| v.parse::<AnyIpCidr>().unwrap()).collect())
+            .unwrap_or_default(),
+        info: matches.is_present("info"),
+        distance: matches.values_of("distance").map(|values| {
+            let ips: Vec<IpAddr> = values.map(|v| v.parse::<IpAddr>().unwrap()).collect();
+            (ips[0], ips[1])
+        }),
+        max_data_age: Duration::from_secs(
+            get_value::<u64>(&matches, "max-data-age").unwrap_or(30) * 86400,
+        ),
         query: get_string_value(&matches, "query"),
-        verbose: matches.is_present("verbose"),
+        format,
+        projection,
+        detect_wildcard: matches.is_present("detect-wildcard"),
+        enumerate: get_string_value(&matches, "enumerate"),
+        wordlist: get_string_value(&matches, "wordlist").map(|w| PathBuf::from(w)),
+        parallelism: get_value::<usize>(&matches, "parallelism").unwrap_or(32),
+        batch: get_string_value(&matches, "batch"),
+        batch_order: get_string_value(&matches, "batch-order")
+            .and_then(|o| BatchOrder::from_str(&o))
+            .unwrap_or(BatchOrder::Input),
+        log_level: if matches.is_present("verbose") {
+            LogLevel::Debug
+        } else {
+            get_string_value(&matches, "log-level")
+                .and_then(|l| LogLevel::from_name(&l))
+                .unwrap_or(LogLevel::Info)
+        },
+        log_format: get_string_value(&matches, "log-format")
+            .and_then(|f| LogFormat::from_name(&f))
+            .unwrap_or(LogFormat::Text),
     }
 }
 
+fn get_maxmind_database_files(matches: &ArgMatches) -> Vec<PathBuf> {
+    let mut mm_files: Vec<PathBuf> = vec![
+        files::get_default_maxmind_asn_path(),
+        files::get_default_maxmind_country_path(),
+    ]
+    .into_iter()
+    .filter(|f| f.exists())
+    .collect();
+    if let Some(values) = matches.values_of("maxmind-database") {
+        mm_files.extend(values.map(|v| PathBuf::from(v)));
+    }
+    mm_files
+}
+
+// Names become keys under 'extra', a second file with the same name would silently
+// replace the first one.
+fn get_custom_mmdb_files(matches: &ArgMatches) -> Vec<(String, PathBuf)> {
+    let mut mmdb_files: Vec<(String, PathBuf)> = Vec::new();
+    for (name, path) in matches
+        .values_of("mmdb")
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.split_once('='))
+    {
+        if mmdb_files.iter().any(|(existing, _)| existing == name) {
+            clap::Error::with_description(
+                &format!("the --mmdb name '{}' is used more than once", name),
+                ErrorKind::ArgumentConflict,
+            )
+            .exit();
+        }
+        mmdb_files.push((name.to_owned(), PathBuf::from(path)));
+    }
+    mmdb_files
+}
+
+fn get_rate_limit(matches: &ArgMatches, key: &str) -> Option<RateLimit> {
+    let burst = matches
+        .value_of("rate-limit-burst")
+        .map(|b| b.parse::<f64>().unwrap());
+    matches
+        .value_of(key)
+        .map(|rate| RateLimit::new(rate.parse::<f64>().unwrap(), burst))
+}
+
 fn get_file_path_or(matches: &ArgMatches, key: &str, path: PathBuf) -> PathBuf {
     get_string_value(matches, key)
         .map(|m| PathBuf::from(m))
@@ -55,14 +207,43 @@ fn get_value<T: FromStr>(matches: &ArgMatches, key: &str) -> Result<T, T::Err> {
 
 #[derive(Debug)]
 pub struct LookupConfig {
-    pub host: IpAddr,
-    pub port: u16,
+    pub listeners: Vec<Listener>,
+    pub unix_socket_mode: u32,
+    pub max_batch_size: usize,
     pub resolver_host: Option<IpAddr>,
     pub resolver_port: u16,
     pub maxmind_city_database_file: PathBuf,
+    pub maxmind_database_files: Vec<PathBuf>,
+    pub custom_mmdb_files: Vec<(String, PathBuf)>,
     pub asn_database_file: PathBuf,
     pub ip_asn_database_file: PathBuf,
+    pub public_suffix_list_file: PathBuf,
     pub daemon: bool,
+    pub legacy_routes: bool,
+    pub admin_routes: bool,
+    pub required_sources: Vec<String>,
+    pub tls: Option<TlsOptions>,
+    pub api_keys_file: Option<PathBuf>,
+    pub key_rate_limit: Option<RateLimit>,
+    pub ip_rate_limit: Option<RateLimit>,
+    pub cors: Option<CorsOptions>,
+    pub cache_max_age: Duration,
+    pub shutdown_delay: Duration,
+    pub shutdown_timeout: Duration,
+    pub reload_interval: Option<Duration>,
+    pub trusted_proxies: Vec<AnyIpCidr>,
+    pub info: bool,
+    pub distance: Option<(IpAddr, IpAddr)>,
+    pub max_data_age: Duration,
     pub query: Option<String>,
-    pub verbose: bool,
+    pub format: OutputFormat,
+    pub projection: Projection,
+    pub detect_wildcard: bool,
+    pub enumerate: Option<String>,
+    pub wordlist: Option<PathBuf>,
+    pub parallelism: usize,
+    pub batch: Option<String>,
+    pub batch_order: BatchOrder,
+    pub log_level: LogLevel,
+    pub log_format: LogFormat,
 }
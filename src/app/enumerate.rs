@@ -0,0 +1,59 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use futures::StreamExt;
+
+use common::format::{self, OutputFormat};
+use common::logging;
+use common::lookup::LookupHandler;
+use common::projection::Projection;
+
+use crate::input;
+
+pub async fn enumerate_subdomains(
+    handler: LookupHandler,
+    zone: String,
+    wordlist: &Path,
+    parallelism: usize,
+    output_format: OutputFormat,
+    projection: &Projection,
+) -> std::io::Result<()> {
+    let zone = zone.trim_end_matches('.').to_owned();
+    let file = File::open(wordlist)?;
+    let probe = handler.probe_wildcard(&zone).await;
+    if probe.is_wildcard() {
+        logging::warn(
+            "zone has wildcard records, filtering matching hosts",
+            &[("zone", &zone)],
+        );
+    }
+
+    let mut error = None;
+    let handler = &handler;
+    let probe = &probe;
+    let zone = &zone;
+    let mut found = Box::pin(
+        input::read_lines(BufReader::new(file), &mut error)
+            .map(|word| async move {
+                let name = format!("{}.{}", word, zone);
+                handler.lookup_subdomain(name, probe).await
+            })
+            .buffer_unordered(parallelism),
+    );
+
+    if let Some(header) = output_format.header(projection) {
+        print!("{}", header);
+    }
+    while let Some(result) = found.next().await {
+        if let Some(host) = result {
+            print!("{}", format::format_item(&host, output_format, projection));
+        }
+    }
+    drop(found);
+
+    match error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
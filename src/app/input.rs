@@ -0,0 +1,41 @@
+use std::io::{self, BufRead};
+use std::thread;
+
+use futures::{future, stream, Stream, StreamExt};
+use tokio::sync::mpsc;
+
+// Reads trimmed, non-empty lines that aren't comments on a separate thread so a slow
+// stdin or file never blocks the runtime. The stream ends at the first read error, which
+// is left in `error` for the caller to report.
+pub fn read_lines<'a, R>(
+    reader: R,
+    error: &'a mut Option<io::Error>,
+) -> impl Stream<Item = String> + 'a
+where
+    R: BufRead + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(64);
+    thread::spawn(move || {
+        for line in reader.lines() {
+            let failed = line.is_err();
+            if sender.blocking_send(line).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|line| (line, receiver))
+    })
+    .scan(error, |error, line| {
+        future::ready(match line {
+            Ok(line) => Some(line),
+            Err(e) => {
+                **error = Some(e);
+                None
+            }
+        })
+    })
+    .map(|line| line.trim().to_owned())
+    .filter(|line| future::ready(!line.is_empty() && !line.starts_with('#')))
+}
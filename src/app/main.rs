@@ -1,72 +1,195 @@
+extern crate cidr;
 extern crate clap;
 extern crate common;
+extern crate futures;
 extern crate serde_json;
 
-use common::asn;
+use std::sync::Arc;
+
+use common::access;
+use common::data::{self, DataFilePaths};
 use common::dns;
-use common::ip;
+use common::logging;
 use common::lookup;
-use common::maxmind;
+use common::metrics::Metrics;
+use common::reload;
+use common::shutdown;
 
-use common::lookup::LookupHandler;
+use common::format::{self, OutputFormat};
+use common::lookup::{DomainLookupOptions, LookupHandler};
+use common::projection::Projection;
 use common::service::LookupService;
-use std::net::IpAddr;
 
+mod batch;
 mod cli;
 mod config;
-
-use config::LookupConfig;
+mod enumerate;
+mod input;
 
 #[tokio::main]
 async fn main() {
     let conf = config::load_config();
+    logging::init(conf.log_level, conf.log_format);
 
-    vlog(&conf, "loading asn database");
-    let asn_database = asn::load_asn_database(&conf.asn_database_file).unwrap();
-
-    vlog(&conf, "loading ip database");
-    let ip_asn_database =
-        ip::load_ip_asn_database(&conf.ip_asn_database_file, &asn_database).unwrap();
-
-    vlog(&conf, "loading maxmind city database");
-    let maxmind_database = maxmind::load_maxmind_database(&conf.maxmind_city_database_file);
+    let mut maxmind_files = vec![conf.maxmind_city_database_file.clone()];
+    maxmind_files.extend(conf.maxmind_database_files.iter().cloned());
+    let data_paths = DataFilePaths {
+        asn_database_file: conf.asn_database_file.clone(),
+        ip_asn_database_file: conf.ip_asn_database_file.clone(),
+        maxmind_database_files: maxmind_files,
+        custom_mmdb_files: conf.custom_mmdb_files.clone(),
+        public_suffix_list_file: conf.public_suffix_list_file.clone(),
+    };
+    let lookup_data = data::load_lookup_data(&data_paths);
 
-    vlog(&conf, "Creating dns resolver");
+    logging::debug("creating dns resolver", &[]);
+    let metrics = Arc::new(Metrics::default());
     let dns_resolver_handle =
-        dns::create_dns_resolver_handle(conf.resolver_host, conf.resolver_port);
+        dns::create_dns_resolver_handle(conf.resolver_host, conf.resolver_port, metrics.clone());
 
-    vlog(&conf, "Creating lookup handler");
+    logging::debug("creating lookup handler", &[]);
     let lookup_handler =
-        lookup::create_lookup_handler(ip_asn_database, maxmind_database, dns_resolver_handle);
+        lookup::create_lookup_handler(lookup_data, data_paths, dns_resolver_handle);
+
+    let data_status = lookup_handler.data_status(conf.max_data_age);
+    if conf.info {
+        println!("{}", serde_json::to_string_pretty(&data_status).unwrap());
+        return;
+    }
+    for warning in data_status.warnings.iter() {
+        logging::warn(
+            &format!("{}, consider running net-lookup-updater", warning),
+            &[],
+        );
+    }
 
     if conf.daemon {
-        vlog(&conf, "Starting lookup daemon");
+        logging::debug("starting lookup daemon", &[]);
+        let api_keys = match &conf.api_keys_file {
+            Some(path) => match access::load_api_keys(path) {
+                Ok(keys) => {
+                    logging::info(
+                        "loaded api keys",
+                        &[("path", &path.display()), ("keys", &keys.len())],
+                    );
+                    Some(keys)
+                }
+                Err(err) => {
+                    logging::error(
+                        "unable to load api keys, stopping",
+                        &[("path", &path.display()), ("error", &err)],
+                    );
+                    std::process::exit(1);
+                }
+            },
+            None => None,
+        };
+        let access_control =
+            access::create_access_control(api_keys, conf.key_rate_limit, conf.ip_rate_limit);
+        let sighup = reload::listen_for_sighup();
+        reload::watch_data_files(lookup_handler.clone(), conf.reload_interval, sighup.clone());
+        let shutdown = shutdown::listen_for_signals(conf.shutdown_delay);
         let service = LookupService {
             handler: lookup_handler,
+            max_batch_size: conf.max_batch_size,
+            parallelism: conf.parallelism,
+            max_data_age: conf.max_data_age,
+            legacy_routes: conf.legacy_routes,
+            admin_routes: conf.admin_routes,
+            trusted_proxies: conf.trusted_proxies.clone(),
+            access: Arc::new(access_control),
+            metrics,
+            tls: conf.tls.clone(),
+            socket_mode: conf.unix_socket_mode,
+            cors: conf.cors.clone(),
+            cache_max_age: conf.cache_max_age,
+            required_sources: conf.required_sources.clone(),
+            shutdown,
+            drain_timeout: conf.shutdown_timeout,
+            sighup,
         };
-        service.start(conf.host, conf.port).await;
+        if let Err(err) = service.start(&conf.listeners).await {
+            logging::error("unable to start lookup service", &[("error", &err)]);
+            std::process::exit(1);
+        }
+        logging::info("lookup service stopped", &[]);
+    } else if conf.distance.is_some() {
+        let (from, to) = conf.distance.unwrap();
+        let result = lookup_handler.lookup_distance(from, to).await;
+        print!(
+            "{}",
+            format::format_document(&result, conf.format, &conf.projection)
+        );
+    } else if conf.batch.is_some() {
+        let source = conf.batch.clone().unwrap();
+        let options = DomainLookupOptions {
+            detect_wildcard: conf.detect_wildcard,
+        };
+        let result = batch::execute_batch(
+            lookup_handler,
+            &source,
+            options,
+            conf.parallelism,
+            conf.batch_order,
+            conf.format,
+            &conf.projection,
+        )
+        .await;
+        if let Err(err) = result {
+            logging::error(
+                "unable to read batch input, stopping",
+                &[("source", &source), ("error", &err)],
+            );
+            std::process::exit(1);
+        }
+    } else if conf.enumerate.is_some() {
+        let zone = conf.enumerate.clone().unwrap();
+        let wordlist = conf.wordlist.clone().unwrap();
+        let result = enumerate::enumerate_subdomains(
+            lookup_handler,
+            zone,
+            &wordlist,
+            conf.parallelism,
+            conf.format,
+            &conf.projection,
+        )
+        .await;
+        if let Err(err) = result {
+            logging::error(
+                "unable to read wordlist, stopping",
+                &[("wordlist", &wordlist.display()), ("error", &err)],
+            );
+            std::process::exit(1);
+        }
     } else if conf.query.is_some() {
-        execute_query(lookup_handler, conf.query.unwrap()).await;
+        let options = DomainLookupOptions {
+            detect_wildcard: conf.detect_wildcard,
+        };
+        let query = conf.query.clone().unwrap();
+        execute_query(
+            lookup_handler,
+            query,
+            options,
+            conf.format,
+            &conf.projection,
+        )
+        .await;
     } else {
-        println!("ERROR: No query provided, stopping.");
+        logging::error("no query provided, stopping", &[]);
         std::process::exit(1);
     }
 }
 
-async fn execute_query(handler: LookupHandler, query: String) {
-    let ip_result = query.parse::<IpAddr>();
-    if ip_result.is_ok() {
-        let ip = ip_result.unwrap();
-        let result = handler.lookup_ip_sync(ip).await;
-        println!("{}", serde_json::to_string(&result).unwrap());
-    } else {
-        let result = handler.lookup_domain_sync(query).await;
-        println!("{}", serde_json::to_string(&result).unwrap());
-    }
-}
-
-fn vlog(conf: &LookupConfig, msg: &str) {
-    if conf.verbose {
-        println!("{}", msg);
-    }
+async fn execute_query(
+    handler: LookupHandler,
+    query: String,
+    options: DomainLookupOptions,
+    output_format: OutputFormat,
+    projection: &Projection,
+) {
+    let result = handler.lookup(query, &options).await;
+    print!(
+        "{}",
+        format::format_document(&result, output_format, projection)
+    );
 }
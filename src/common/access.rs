@@ -0,0 +1,567 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::net::{IpAddr, Ipv6Addr};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use hyper::{Body, Request, Uri};
+
+static MAX_IDLE_BUCKETS: usize = 10_000;
+static SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+static MIN_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+// Each line of the key file is "<key> [name] [requests-per-second] [admin]", the name is
+// used in logs and metrics so the key itself never shows up there. Only keys marked admin
+// may call the admin endpoints.
+pub fn load_api_keys(file_path: &Path) -> io::Result<ApiKeys> {
+    let file = File::open(file_path)?;
+    let mut keys = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let key = parts.next().unwrap().to_owned();
+        let name = parts
+            .next()
+            .map(|n| n.to_owned())
+            .unwrap_or_else(|| format!("key-{}", keys.len() + 1));
+        let mut rate = None;
+        let mut admin = false;
+        for part in parts {
+            match part.parse::<f64>() {
+                _ if part == "admin" => admin = true,
+                Ok(value) if rate.is_none() && value > 0.0 && value.is_finite() => {
+                    rate = Some(value)
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                        "invalid value '{}' for api key {}, expected a positive rate or 'admin'",
+                        part, name
+                    ),
+                    ))
+                }
+            }
+        }
+        keys.insert(key, ApiKey { name, rate, admin });
+    }
+    Ok(ApiKeys { keys })
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub name: String,
+    pub rate: Option<f64>,
+    pub admin: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiKeys {
+    keys: HashMap<String, ApiKey>,
+}
+
+impl ApiKeys {
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn get(&self, key: &str) -> Option<&ApiKey> {
+        self.keys.get(key)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub rate: f64,
+    pub burst: f64,
+}
+
+impl RateLimit {
+    pub fn new(rate: f64, burst: Option<f64>) -> RateLimit {
+        RateLimit {
+            rate,
+            burst: burst.unwrap_or(rate).max(1.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+    limit: RateLimit,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Instant) -> TokenBucket {
+        TokenBucket {
+            tokens: limit.burst,
+            updated: now,
+            limit,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst);
+        self.updated = now;
+    }
+
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.limit.rate,
+            ))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    buckets: HashMap<String, TokenBucket>,
+    last_sweep: Instant,
+}
+
+#[derive(Debug)]
+struct RateLimiter {
+    buckets: Mutex<Buckets>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> RateLimiter {
+        RateLimiter {
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+        }
+    }
+}
+
+impl RateLimiter {
+    fn check(&self, id: &str, limit: RateLimit) -> Result<(), Duration> {
+        self.check_at(id, limit, Instant::now())
+    }
+
+    fn check_at(&self, id: &str, limit: RateLimit, now: Instant) -> Result<(), Duration> {
+        let mut state = self.buckets.lock().unwrap();
+        // Sweeping walks every bucket, so it only runs periodically, or at most once a
+        // second while the map is over its size limit. Buckets that have refilled
+        // completely behave exactly like new ones and can be dropped.
+        let since_sweep = now.saturating_duration_since(state.last_sweep);
+        if since_sweep >= SWEEP_INTERVAL
+            || (state.buckets.len() > MAX_IDLE_BUCKETS && since_sweep >= MIN_SWEEP_INTERVAL)
+        {
+            state.buckets.retain(|_, bucket| {
+                bucket.refill(now);
+                bucket.tokens < bucket.limit.burst
+            });
+            state.last_sweep = now;
+        }
+        let bucket = state
+            .buckets
+            .entry(id.to_owned())
+            .or_insert_with(|| TokenBucket::new(limit, now));
+        bucket.limit = limit;
+        bucket.take(now)
+    }
+}
+
+// Clients usually get a whole IPv6 /64, so addresses within one share a bucket.
+fn ip_bucket_id(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            let prefix = Ipv6Addr::new(
+                segments[0],
+                segments[1],
+                segments[2],
+                segments[3],
+                0,
+                0,
+                0,
+                0,
+            );
+            format!("{}/64", prefix)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AccessDenied {
+    MissingKey,
+    InvalidKey,
+    NotAdmin,
+    RateLimited {
+        limit: &'static str,
+        key: Option<String>,
+        retry_after: Duration,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct AccessControl {
+    api_keys: Option<ApiKeys>,
+    key_limit: Option<RateLimit>,
+    ip_limit: Option<RateLimit>,
+    key_buckets: RateLimiter,
+    ip_buckets: RateLimiter,
+}
+
+pub fn create_access_control(
+    api_keys: Option<ApiKeys>,
+    key_limit: Option<RateLimit>,
+    ip_limit: Option<RateLimit>,
+) -> AccessControl {
+    AccessControl {
+        api_keys,
+        key_limit,
+        ip_limit,
+        ..AccessControl::default()
+    }
+}
+
+impl AccessControl {
+    pub fn requires_key(&self) -> bool {
+        self.api_keys.is_some()
+    }
+
+    // Returns the name of the api key used for the request, if keys are configured. Admin
+    // requests additionally need a key marked admin, without keys they are only accepted
+    // over unix sockets whose file permissions already restrict who can connect.
+    pub fn check(
+        &self,
+        req: &Request<Body>,
+        client_ip: IpAddr,
+        admin: bool,
+        unix_socket: bool,
+    ) -> Result<Option<String>, AccessDenied> {
+        let api_key = match &self.api_keys {
+            Some(keys) => {
+                let key = get_api_key(req).ok_or(AccessDenied::MissingKey)?;
+                Some(keys.get(&key).ok_or(AccessDenied::InvalidKey)?)
+            }
+            None => None,
+        };
+        match api_key {
+            Some(key) if admin && !key.admin => return Err(AccessDenied::NotAdmin),
+            None if admin && !unix_socket => return Err(AccessDenied::NotAdmin),
+            _ => {}
+        }
+
+        if let Some(limit) = self.ip_limit {
+            self.ip_buckets
+                .check(&ip_bucket_id(client_ip), limit)
+                .map_err(|retry_after| AccessDenied::RateLimited {
+                    limit: "ip",
+                    key: api_key.map(|k| k.name.clone()),
+                    retry_after,
+                })?;
+        }
+        if let Some(api_key) = api_key {
+            let limit = match (api_key.rate, self.key_limit) {
+                (Some(rate), limit) => Some(RateLimit::new(rate, limit.map(|l| l.burst))),
+                (None, limit) => limit,
+            };
+            if let Some(limit) = limit {
+                self.key_buckets
+                    .check(&api_key.name, limit)
+                    .map_err(|retry_after| AccessDenied::RateLimited {
+                        limit: "key",
+                        key: Some(api_key.name.clone()),
+                        retry_after,
+                    })?;
+            }
+        }
+        Ok(api_key.map(|k| k.name.clone()))
+    }
+}
+
+fn get_api_key(req: &Request<Body>) -> Option<String> {
+    let headers = req.headers();
+    headers
+        .get("X-API-Key")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_owned())
+        .or_else(|| {
+            headers
+                .get("Authorization")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .map(|value| value.trim().to_owned())
+        })
+        .or_else(|| {
+            req.uri().query().and_then(|query| {
+                query
+                    .split('&')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| *key == "api_key")
+                    .map(|(_, value)| value.to_owned())
+            })
+        })
+        .filter(|key| !key.is_empty())
+}
+
+// Keeps api keys passed as a query parameter out of the access log.
+pub fn redact_query(uri: &Uri) -> String {
+    match uri.query() {
+        Some(query) => {
+            let query: Vec<&str> = query
+                .split('&')
+                .map(|pair| match pair.split_once('=') {
+                    Some(("api_key", _)) => "api_key=REDACTED",
+                    _ => pair,
+                })
+                .collect();
+            format!("{}?{}", uri.path(), query.join("&"))
+        }
+        None => uri.path().to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn limit(rate: f64, burst: f64) -> RateLimit {
+        RateLimit::new(rate, Some(burst))
+    }
+
+    fn request(uri: &str, headers: &[(&'static str, &str)]) -> Request<Body> {
+        let mut builder = Request::builder().uri(uri);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    fn write_keys(name: &str, contents: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("net-lookup-keys-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn bucket_allows_burst_then_reports_retry_after() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        let limit = limit(2.0, 3.0);
+        for _ in 0..3 {
+            assert!(limiter.check_at("a", limit, now).is_ok());
+        }
+        let retry_after = limiter.check_at("a", limit, now).unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        let limit = limit(2.0, 1.0);
+        assert!(limiter.check_at("a", limit, now).is_ok());
+        assert!(limiter.check_at("a", limit, now).is_err());
+        let later = now + Duration::from_millis(250);
+        assert_eq!(
+            limiter.check_at("a", limit, later).unwrap_err(),
+            Duration::from_millis(250)
+        );
+        assert!(limiter
+            .check_at("a", limit, now + Duration::from_millis(500))
+            .is_ok());
+    }
+
+    #[test]
+    fn buckets_are_independent() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        assert!(limiter.check_at("a", limit(1.0, 1.0), now).is_ok());
+        assert!(limiter.check_at("a", limit(1.0, 1.0), now).is_err());
+        assert!(limiter.check_at("b", limit(1.0, 1.0), now).is_ok());
+    }
+
+    #[test]
+    fn sweep_keeps_each_bucket_limit() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        // A slow key that emptied its bucket must not be refilled with a faster key's rate.
+        assert!(limiter.check_at("slow", limit(0.01, 1.0), now).is_ok());
+        let later = now + SWEEP_INTERVAL;
+        assert!(limiter.check_at("fast", limit(1000.0, 1.0), later).is_ok());
+        assert!(limiter.check_at("slow", limit(0.01, 1.0), later).is_err());
+    }
+
+    #[test]
+    fn sweep_drops_full_buckets() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        assert!(limiter.check_at("a", limit(10.0, 1.0), now).is_ok());
+        assert!(limiter
+            .check_at("b", limit(10.0, 1.0), now + SWEEP_INTERVAL)
+            .is_ok());
+        let state = limiter.buckets.lock().unwrap();
+        assert!(!state.buckets.contains_key("a"));
+        assert!(state.buckets.contains_key("b"));
+    }
+
+    #[test]
+    fn ipv6_clients_share_a_64() {
+        let a = ip_bucket_id("2001:db8:1:2:aaaa::1".parse().unwrap());
+        let b = ip_bucket_id("2001:db8:1:2:bbbb::2".parse().unwrap());
+        let c = ip_bucket_id("2001:db8:1:3::1".parse().unwrap());
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(ip_bucket_id("192.0.2.1".parse().unwrap()), "192.0.2.1");
+    }
+
+    #[test]
+    fn ip_limit_applies_per_64() {
+        let access = create_access_control(None, None, Some(limit(1.0, 1.0)));
+        let req = request("/ip/1.1.1.1", &[]);
+        assert!(access
+            .check(&req, "2001:db8::1".parse().unwrap(), false, false)
+            .is_ok());
+        match access.check(&req, "2001:db8::ffff".parse().unwrap(), false, false) {
+            Err(AccessDenied::RateLimited { limit: "ip", .. }) => {}
+            other => panic!("expected ip rate limit, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn load_api_keys_rejects_invalid_rates() {
+        for rate in ["0", "-1", "NaN", "inf", "fast", "1 2"] {
+            let path = write_keys(rate, &format!("secret team {}\n", rate));
+            assert!(load_api_keys(&path).is_err(), "rate {} was accepted", rate);
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn load_api_keys_parses_names_and_rates() {
+        let path = write_keys("valid", "# comment\n\nalpha team-a 2.5\nbeta\n");
+        let keys = load_api_keys(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys.get("alpha").unwrap().name, "team-a");
+        assert_eq!(keys.get("alpha").unwrap().rate, Some(2.5));
+        assert_eq!(keys.get("beta").unwrap().rate, None);
+    }
+
+    #[test]
+    fn api_key_is_required_and_checked() {
+        let path = write_keys("check", "alpha team-a\n");
+        let keys = load_api_keys(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        let access = create_access_control(Some(keys), None, None);
+        let ip = "192.0.2.1".parse().unwrap();
+
+        assert!(matches!(
+            access.check(&request("/ip/1.1.1.1", &[]), ip, false, false),
+            Err(AccessDenied::MissingKey)
+        ));
+        assert!(matches!(
+            access.check(
+                &request("/ip/1.1.1.1", &[("X-API-Key", "wrong")]),
+                ip,
+                false,
+                false
+            ),
+            Err(AccessDenied::InvalidKey)
+        ));
+        for req in [
+            request("/ip/1.1.1.1", &[("X-API-Key", "alpha")]),
+            request("/ip/1.1.1.1", &[("Authorization", "Bearer alpha")]),
+            request("/ip/1.1.1.1?format=csv&api_key=alpha", &[]),
+        ] {
+            assert_eq!(
+                access.check(&req, ip, false, false).unwrap(),
+                Some("team-a".to_owned())
+            );
+        }
+    }
+
+    #[test]
+    fn key_rate_from_file_overrides_default() {
+        let path = write_keys("override", "alpha team-a 1\nbeta team-b\n");
+        let keys = load_api_keys(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        let access = create_access_control(Some(keys), Some(limit(100.0, 1.0)), None);
+        let ip = "192.0.2.1".parse().unwrap();
+        let alpha = request("/", &[("X-API-Key", "alpha")]);
+        let beta = request("/", &[("X-API-Key", "beta")]);
+
+        assert!(access.check(&alpha, ip, false, false).is_ok());
+        match access.check(&alpha, ip, false, false) {
+            Err(AccessDenied::RateLimited {
+                limit: "key",
+                key,
+                retry_after,
+            }) => {
+                assert_eq!(key, Some("team-a".to_owned()));
+                assert!(retry_after > Duration::from_millis(900));
+            }
+            other => panic!("expected key rate limit, got {:?}", other),
+        }
+        assert!(access.check(&beta, ip, false, false).is_ok());
+    }
+
+    #[test]
+    fn admin_requests_need_an_admin_key() {
+        let path = write_keys("admin", "alpha team-a\nroot ops 5 admin\n");
+        let keys = load_api_keys(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        let access = create_access_control(Some(keys), None, None);
+        let ip = "192.0.2.1".parse().unwrap();
+        let alpha = request("/admin/reload", &[("X-API-Key", "alpha")]);
+        let root = request("/admin/reload", &[("X-API-Key", "root")]);
+
+        assert!(matches!(
+            access.check(&alpha, ip, true, false),
+            Err(AccessDenied::NotAdmin)
+        ));
+        assert_eq!(
+            access.check(&root, ip, true, false).unwrap(),
+            Some("ops".to_owned())
+        );
+        assert!(access.check(&alpha, ip, false, false).is_ok());
+    }
+
+    #[test]
+    fn admin_requests_without_keys_need_a_unix_socket() {
+        let access = create_access_control(None, None, None);
+        let ip = "127.0.0.1".parse().unwrap();
+        let req = request("/admin/reload", &[]);
+
+        assert!(matches!(
+            access.check(&req, ip, true, false),
+            Err(AccessDenied::NotAdmin)
+        ));
+        assert_eq!(access.check(&req, ip, true, true).unwrap(), None);
+        assert_eq!(access.check(&req, ip, false, false).unwrap(), None);
+    }
+
+    #[test]
+    fn redact_query_hides_api_keys() {
+        let uri: Uri = "/ip/1.1.1.1?api_key=secret&format=csv".parse().unwrap();
+        assert_eq!(
+            redact_query(&uri),
+            "/ip/1.1.1.1?api_key=REDACTED&format=csv"
+        );
+    }
+}
@@ -4,6 +4,9 @@ use std::io::{BufRead, BufReader, Result};
 use std::path::Path;
 use std::sync::Arc;
 
+use crate::logging;
+use crate::status::{self, DataFileInfo};
+
 pub fn load_asn_database(file_path: &Path) -> Result<AsnDatabase> {
     let file = File::open(file_path)?;
     let mut asn_map = HashMap::new();
@@ -13,11 +16,13 @@ pub fn load_asn_database(file_path: &Path) -> Result<AsnDatabase> {
             let asn = parse_autonomous_system_number(line.unwrap());
             asn_map.insert(asn.id, Arc::new(asn));
         } else {
-            println!("[ASN] skipping non utf8");
+            logging::warn("skipping non utf8 line", &[("file", &file_path.display())]);
         }
     }
 
-    Ok(AsnDatabase { asn_map })
+    let mut info = status::create_data_file_info("asn", file_path);
+    info.records = Some(asn_map.len());
+    Ok(AsnDatabase { asn_map, info })
 }
 
 fn parse_autonomous_system_number(line: String) -> AutonomousSystemNumber {
@@ -59,9 +64,32 @@ pub struct AutonomousSystemNumber {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AsnDatabase {
     asn_map: HashMap<u32, Arc<AutonomousSystemNumber>>,
+    info: DataFileInfo,
+}
+
+impl AutonomousSystemNumber {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn handle(&self) -> &str {
+        &self.handle
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|n| n.as_str())
+    }
+
+    pub fn country(&self) -> &str {
+        &self.country
+    }
 }
 
 impl AsnDatabase {
+    pub fn info(&self) -> &DataFileInfo {
+        &self.info
+    }
+
     pub fn lookup(&self, id: u32) -> Option<Arc<AutonomousSystemNumber>> {
         self.asn_map.get(&id).map(|r| r.clone())
     }
@@ -0,0 +1,162 @@
+use std::net::IpAddr;
+
+use serde_json::Value;
+
+use crate::geo::{self, GeoPoint};
+use crate::idn;
+use crate::lookup::{DomainLookupOptions, LookupHandler, LookupResult};
+
+// The longest domain name DNS can carry, ip addresses are always shorter.
+static MAX_QUERY_LEN: usize = 253;
+
+pub fn parse_batch_body(body: &[u8]) -> Result<Vec<Value>, String> {
+    let body = std::str::from_utf8(body).map_err(|_| "request body is not valid utf8")?;
+    let trimmed = body.trim();
+    if trimmed.starts_with('[') {
+        serde_json::from_str::<Vec<Value>>(trimmed)
+            .map_err(|err| format!("request body is not a valid json array: {}", err))
+    } else {
+        Ok(trimmed
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                serde_json::from_str::<Value>(line).unwrap_or(Value::String(line.to_owned()))
+            })
+            .collect())
+    }
+}
+
+pub async fn lookup_batch_item(
+    handler: &LookupHandler,
+    query: Value,
+    options: &DomainLookupOptions,
+) -> BatchLookupResult {
+    match validate_query(&query) {
+        Ok(q) => BatchLookupResult {
+            result: Some(handler.lookup(q, options).await),
+            error: None,
+            distance_km: None,
+            query,
+        },
+        Err(err) => BatchLookupResult {
+            result: None,
+            error: Some(err),
+            distance_km: None,
+            query,
+        },
+    }
+}
+
+pub fn filter_near(
+    mut item: BatchLookupResult,
+    near: &GeoPoint,
+    radius_km: Option<f64>,
+) -> Option<BatchLookupResult> {
+    item.distance_km = item
+        .result
+        .as_ref()
+        .and_then(|result| geo::nearest_distance_km(result, near));
+    match (item.distance_km, radius_km) {
+        (_, None) => Some(item),
+        (Some(distance), Some(radius)) if distance <= radius => Some(item),
+        _ => None,
+    }
+}
+
+fn validate_query(query: &Value) -> Result<String, String> {
+    let query = match query.as_str() {
+        Some(q) => q.trim(),
+        None => return Err("query must be a string".to_owned()),
+    };
+    if query.is_empty() {
+        Err("query must not be empty".to_owned())
+    } else if query.len() > MAX_QUERY_LEN {
+        Err(format!("query is longer than {} bytes", MAX_QUERY_LEN))
+    } else if query.parse::<IpAddr>().is_ok() || idn::to_ascii(query).is_some() {
+        Ok(query.to_owned())
+    } else {
+        Err(format!("'{}' is not a valid ip address or domain", query))
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BatchLookupResult {
+    pub query: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<LookupResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_km: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed(query: Value) -> BatchLookupResult {
+        BatchLookupResult {
+            query,
+            result: None,
+            error: Some("invalid query".to_owned()),
+            distance_km: None,
+        }
+    }
+
+    #[test]
+    fn parses_json_arrays() {
+        let queries = parse_batch_body(b" [\"1.1.1.1\", \"example.com\"]\n").unwrap();
+        assert_eq!(
+            queries,
+            vec![Value::from("1.1.1.1"), Value::from("example.com")]
+        );
+        assert!(parse_batch_body(b"[\"1.1.1.1\",").is_err());
+        assert!(parse_batch_body(b"\xff\xfe").is_err());
+    }
+
+    #[test]
+    fn parses_newline_delimited_queries() {
+        let queries = parse_batch_body(b"1.1.1.1\n\n  \"example.com\"  \r\n42\n").unwrap();
+        assert_eq!(
+            queries,
+            vec![
+                Value::from("1.1.1.1"),
+                Value::from("example.com"),
+                Value::from(42)
+            ]
+        );
+        assert!(parse_batch_body(b"\n  \n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_non_string_and_oversized_items() {
+        assert!(validate_query(&Value::from(42)).is_err());
+        assert!(validate_query(&Value::Null).is_err());
+        assert!(validate_query(&Value::from("  ")).is_err());
+        assert_eq!(
+            validate_query(&Value::from(" 1.1.1.1 ")).unwrap(),
+            "1.1.1.1"
+        );
+
+        let longest = format!("{}.com", "a.".repeat(124) + "a");
+        assert_eq!(longest.len(), MAX_QUERY_LEN);
+        assert!(validate_query(&Value::from(longest.as_str())).is_ok());
+        let too_long = format!("a{}", longest);
+        assert_eq!(
+            validate_query(&Value::from(too_long)).unwrap_err(),
+            "query is longer than 253 bytes"
+        );
+    }
+
+    #[test]
+    fn filter_near_drops_items_without_distance_only_with_a_radius() {
+        let near = GeoPoint {
+            latitude: 51.5,
+            longitude: -0.1,
+        };
+        let item = filter_near(failed(Value::from("x")), &near, None).unwrap();
+        assert_eq!(item.distance_km, None);
+        assert!(filter_near(failed(Value::from("x")), &near, Some(100.0)).is_none());
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use hyper::header::HeaderValue;
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+static ALLOWED_HEADERS: &str =
+    "Accept, Accept-Language, Authorization, Content-Type, If-None-Match, X-API-Key, X-Request-Id";
+static EXPOSED_HEADERS: &str = "ETag, Retry-After, X-Request-Id";
+
+#[derive(Debug, Clone)]
+pub struct CorsOptions {
+    pub origins: Vec<String>,
+    pub methods: Vec<Method>,
+    pub max_age: Duration,
+}
+
+impl CorsOptions {
+    fn allowed_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        if self.origins.iter().any(|o| o == "*") {
+            Some(HeaderValue::from_static("*"))
+        } else {
+            let value = origin.to_str().ok()?;
+            self.origins
+                .iter()
+                .find(|o| o.eq_ignore_ascii_case(value))
+                .map(|_| origin.clone())
+        }
+    }
+
+    fn methods(&self) -> String {
+        self.methods
+            .iter()
+            .map(|m| m.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+pub fn parse_methods(value: &str) -> Result<Vec<Method>, String> {
+    value
+        .split(',')
+        .map(|m| m.trim())
+        .filter(|m| !m.is_empty())
+        .map(|m| {
+            Method::from_bytes(m.to_ascii_uppercase().as_bytes())
+                .map_err(|_| format!("'{}' is not a valid http method", m))
+        })
+        .collect()
+}
+
+pub fn is_preflight(req: &Request<Body>) -> bool {
+    req.method() == Method::OPTIONS
+        && req.headers().contains_key("Origin")
+        && req.headers().contains_key("Access-Control-Request-Method")
+}
+
+// Preflights are answered before authentication since browsers never send credentials
+// with them. Disallowed origins get a response without CORS headers, which the browser
+// treats as a rejection.
+pub fn preflight_response(options: &CorsOptions, req: &Request<Body>) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::NO_CONTENT;
+    let allowed = req
+        .headers()
+        .get("Origin")
+        .and_then(|origin| options.allowed_origin(origin));
+    if let Some(origin) = allowed {
+        let headers = response.headers_mut();
+        headers.insert("Access-Control-Allow-Origin", origin);
+        headers.insert(
+            "Access-Control-Allow-Methods",
+            HeaderValue::from_str(&options.methods()).unwrap(),
+        );
+        headers.insert(
+            "Access-Control-Allow-Headers",
+            HeaderValue::from_static(ALLOWED_HEADERS),
+        );
+        headers.insert(
+            "Access-Control-Max-Age",
+            HeaderValue::from(options.max_age.as_secs()),
+        );
+    }
+    response
+        .headers_mut()
+        .append("Vary", HeaderValue::from_static("Origin"));
+    response
+}
+
+pub fn add_cors_headers(
+    options: &CorsOptions,
+    origin: Option<&HeaderValue>,
+    response: &mut Response<Body>,
+) {
+    let headers = response.headers_mut();
+    headers.append("Vary", HeaderValue::from_static("Origin"));
+    if let Some(origin) = origin.and_then(|origin| options.allowed_origin(origin)) {
+        headers.insert("Access-Control-Allow-Origin", origin);
+        headers.insert(
+            "Access-Control-Expose-Headers",
+            HeaderValue::from_static(EXPOSED_HEADERS),
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::asn::{self, AsnDatabase};
+use crate::ip::{self, IpAsnDatabase};
+use crate::logging;
+use crate::maxmind::{self, MaxmindDatabase};
+use crate::psl::{self, PublicSuffixList};
+use crate::status::{self, DataFileInfo, DataSources, DisabledDataSource};
+
+#[derive(Debug, Clone)]
+pub struct DataFilePaths {
+    pub asn_database_file: PathBuf,
+    pub ip_asn_database_file: PathBuf,
+    pub maxmind_database_files: Vec<PathBuf>,
+    pub custom_mmdb_files: Vec<(String, PathBuf)>,
+    pub public_suffix_list_file: PathBuf,
+}
+
+impl DataFilePaths {
+    fn all(&self) -> Vec<&Path> {
+        let mut paths = vec![
+            self.asn_database_file.as_path(),
+            self.ip_asn_database_file.as_path(),
+            self.public_suffix_list_file.as_path(),
+        ];
+        paths.extend(self.maxmind_database_files.iter().map(|p| p.as_path()));
+        paths.extend(self.custom_mmdb_files.iter().map(|(_, p)| p.as_path()));
+        paths
+    }
+
+    pub fn modified_times(&self) -> BTreeMap<PathBuf, Option<SystemTime>> {
+        self.all()
+            .into_iter()
+            .map(|path| {
+                let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+                (path.to_path_buf(), modified)
+            })
+            .collect()
+    }
+}
+
+pub struct LookupData {
+    pub asn_database: Option<AsnDatabase>,
+    pub ip_asn_database: Option<IpAsnDatabase>,
+    pub maxmind_database: MaxmindDatabase,
+    pub public_suffix_list: Option<PublicSuffixList>,
+    pub sources: DataSources,
+    pub modified_times: BTreeMap<PathBuf, Option<SystemTime>>,
+}
+
+pub fn load_lookup_data(paths: &DataFilePaths) -> LookupData {
+    let modified_times = paths.modified_times();
+    let mut sources = DataSources::default();
+
+    logging::debug("loading asn database", &[]);
+    let asn_database = load_data_source(
+        &mut sources,
+        "asn",
+        &paths.asn_database_file,
+        "AS names",
+        asn::load_asn_database,
+        |db| db.info(),
+    );
+
+    logging::debug("loading ip database", &[]);
+    let ip_asn_database = load_data_source(
+        &mut sources,
+        "ip2asn",
+        &paths.ip_asn_database_file,
+        "prefix and ASN lookups",
+        |path| ip::load_ip_asn_database(path, asn_database.as_ref()),
+        |db| db.info(),
+    );
+
+    logging::debug("loading maxmind databases", &[]);
+    let maxmind_database =
+        maxmind::load_maxmind_database(&paths.maxmind_database_files, &paths.custom_mmdb_files);
+    for disabled in maxmind_database.disabled() {
+        warn_disabled_source(disabled, "MaxMind lookups");
+    }
+    sources
+        .files
+        .extend(maxmind_database.infos().iter().cloned());
+    sources
+        .disabled
+        .extend(maxmind_database.disabled().iter().cloned());
+
+    logging::debug("loading public suffix list", &[]);
+    let public_suffix_list = load_data_source(
+        &mut sources,
+        "public-suffix-list",
+        &paths.public_suffix_list_file,
+        "suffix lookups",
+        psl::load_public_suffix_list,
+        |list| list.info(),
+    );
+
+    LookupData {
+        asn_database,
+        ip_asn_database,
+        maxmind_database,
+        public_suffix_list,
+        sources,
+        modified_times,
+    }
+}
+
+impl LookupData {
+    // A reload must not lose a source that is currently serving lookups, and a file that
+    // loads without any records is most likely still being written.
+    pub fn validate_replacement(&self, replacement: &LookupData) -> Result<(), String> {
+        for file in self.sources.files.iter() {
+            if let Some(disabled) = replacement
+                .sources
+                .disabled
+                .iter()
+                .find(|d| d.path == file.path)
+            {
+                return Err(format!(
+                    "{} ({}) failed to load: {}",
+                    disabled.name,
+                    disabled.path.display(),
+                    disabled.reason
+                ));
+            }
+        }
+        for file in replacement.sources.files.iter() {
+            if file.records == Some(0) {
+                return Err(format!(
+                    "{} ({}) has no records",
+                    file.name,
+                    file.path.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn load_data_source<T>(
+    sources: &mut DataSources,
+    name: &str,
+    path: &Path,
+    feature: &str,
+    load: impl FnOnce(&Path) -> io::Result<T>,
+    info: impl FnOnce(&T) -> &DataFileInfo,
+) -> Option<T> {
+    match load(path) {
+        Ok(source) => {
+            sources.files.push(info(&source).clone());
+            Some(source)
+        }
+        Err(err) => {
+            let disabled = status::create_disabled_data_source(name, path, err.to_string());
+            warn_disabled_source(&disabled, feature);
+            sources.disabled.push(disabled);
+            None
+        }
+    }
+}
+
+fn warn_disabled_source(disabled: &DisabledDataSource, feature: &str) {
+    logging::warn(
+        &format!(
+            "unable to load {}, {} disabled. Run net-lookup-updater to download it",
+            disabled.name, feature
+        ),
+        &[
+            ("path", &disabled.path.display()),
+            ("error", &disabled.reason),
+        ],
+    );
+}
@@ -1,16 +1,35 @@
+use std::io;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::str;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 
 use domain::base::iana::Class;
 use domain::base::{Dname, ParsedDname, Question, Rtype};
 use domain::rdata;
 use domain::resolv::stub::conf::ServerConf;
 use domain::resolv::stub::conf::{ResolvConf, Transport};
+use domain::resolv::stub::Answer;
 use domain::resolv::StubResolver;
 
-pub fn create_dns_resolver_handle(host: Option<IpAddr>, port: u16) -> DnsResolverHandle {
+use crate::idn;
+use crate::logging;
+use crate::metrics::Metrics;
+
+pub fn create_dns_resolver_handle(
+    host: Option<IpAddr>,
+    port: u16,
+    metrics: Arc<Metrics>,
+) -> DnsResolverHandle {
     return DnsResolverHandle {
         resolv: create_resolver(host, port),
+        upstream: host
+            .map(|h| SocketAddr::new(h, port).to_string())
+            .unwrap_or("system".to_owned()),
+        metrics,
+        request_id: None,
+        ttl: Arc::new(DnsTtlTracker::default()),
     };
 }
 
@@ -29,39 +48,161 @@ fn create_resolver(resolver_host: Option<IpAddr>, resolver_port: u16) -> StubRes
 #[derive(Clone)]
 pub struct DnsResolverHandle {
     resolv: StubResolver,
+    upstream: String,
+    metrics: Arc<Metrics>,
+    request_id: Option<Arc<str>>,
+    ttl: Arc<DnsTtlTracker>,
+}
+
+// Collects the lowest answer ttl seen while serving one request, used to decide how long
+// the response may be cached.
+#[derive(Debug)]
+struct DnsTtlTracker {
+    min_ttl: AtomicU32,
+    failed: AtomicBool,
+}
+
+impl Default for DnsTtlTracker {
+    fn default() -> DnsTtlTracker {
+        DnsTtlTracker {
+            min_ttl: AtomicU32::new(u32::MAX),
+            failed: AtomicBool::new(false),
+        }
+    }
 }
 
 impl DnsResolverHandle {
+    pub fn with_request_id(&self, request_id: &str) -> DnsResolverHandle {
+        DnsResolverHandle {
+            request_id: Some(Arc::from(request_id)),
+            ttl: Arc::new(DnsTtlTracker::default()),
+            ..self.clone()
+        }
+    }
+
+    pub fn min_ttl(&self) -> Option<u32> {
+        match self.ttl.min_ttl.load(Ordering::Relaxed) {
+            u32::MAX => None,
+            ttl => Some(ttl),
+        }
+    }
+
+    pub fn had_failures(&self) -> bool {
+        self.ttl.failed.load(Ordering::Relaxed)
+    }
+
+    fn request_id(&self) -> &str {
+        self.request_id.as_deref().unwrap_or("-")
+    }
+
+    async fn query(&self, dname: &Dname<Vec<u8>>, rtype: Rtype) -> io::Result<Answer> {
+        let started = Instant::now();
+        let result = self.resolv.query((dname, rtype, Class::In)).await;
+        self.metrics.record_dns_query(
+            &rtype.to_string(),
+            &self.upstream,
+            started.elapsed(),
+            result.is_ok(),
+        );
+        if let Ok(answer) = &result {
+            self.ttl
+                .min_ttl
+                .fetch_min(answer_ttl(answer), Ordering::Relaxed);
+        }
+        if let Err(err) = &result {
+            self.ttl.failed.store(true, Ordering::Relaxed);
+            logging::warn(
+                "dns query failed",
+                &[
+                    ("name", dname),
+                    ("rtype", &rtype),
+                    ("upstream", &self.upstream),
+                    ("error", err),
+                    ("request_id", &self.request_id()),
+                ],
+            );
+        }
+        result
+    }
+
     pub async fn reverse_dns_lookup(&self, ip: IpAddr) -> Vec<String> {
-        match self.resolv.lookup_addr(ip).await {
+        let started = Instant::now();
+        let result = self.resolv.lookup_addr(ip).await;
+        self.metrics.record_dns_query(
+            &Rtype::Ptr.to_string(),
+            &self.upstream,
+            started.elapsed(),
+            result.is_ok(),
+        );
+        match result {
             Ok(addrs) => addrs.iter().map(|n| n.to_string()).collect(),
             Err(err) => {
-                println!("error = {:?}", err);
+                logging::debug(
+                    "reverse dns lookup failed",
+                    &[
+                        ("ip", &ip),
+                        ("upstream", &self.upstream),
+                        ("error", &err),
+                        ("request_id", &self.request_id()),
+                    ],
+                );
                 Vec::new()
             }
         }
     }
 
     pub async fn dns_lookup(&self, domain: String) -> DnsLookupResult {
-        let mut domain = domain.clone();
-        if !domain.ends_with('.') {
-            domain.push('.')
+        match create_dname(&domain) {
+            Some(dname) => create_lookup_future(self, &dname).await,
+            None => DnsLookupResult::empty(),
         }
-        match Dname::<Vec<_>>::from_chars(domain.chars()) {
-            Ok(dname) => create_lookup_future(self.resolv.clone(), &dname).await,
-            Err(_) => DnsLookupResult::empty(),
+    }
+
+    pub async fn host_lookup(&self, host: String) -> Vec<IpAddr> {
+        match create_dname(&host) {
+            Some(dname) => {
+                let a = create_a_lookup_future(self, &dname).await;
+                let aaaa = create_aaaa_lookup_future(self, &dname).await;
+                let mut addrs: Vec<IpAddr> = Vec::new();
+                addrs.append(&mut a.into_iter().map(IpAddr::from).collect());
+                addrs.append(&mut aaaa.into_iter().map(IpAddr::from).collect());
+                addrs
+            }
+            None => Vec::new(),
+        }
+    }
+
+    pub async fn address_lookup(&self, name: String) -> DnsAddressLookupResult {
+        match create_dname(&name) {
+            Some(dname) => DnsAddressLookupResult {
+                a: create_a_lookup_future(self, &dname).await,
+                aaaa: create_aaaa_lookup_future(self, &dname).await,
+                cname: create_cname_lookup_future(self, &dname).await,
+            },
+            None => DnsAddressLookupResult::empty(),
         }
     }
 }
 
-async fn create_lookup_future(resolv: StubResolver, dname: &Dname<Vec<u8>>) -> DnsLookupResult {
-    let a = create_a_lookup_future(resolv.clone(), &dname).await;
-    let aaaa = create_aaaa_lookup_future(resolv.clone(), &dname).await;
-    let cname = create_cname_lookup_future(resolv.clone(), &dname).await;
-    let ns = create_ns_lookup_future(resolv.clone(), &dname).await;
-    let mx = create_mx_lookup_future(resolv.clone(), &dname).await;
-    let txt = create_txt_lookup_future(resolv.clone(), &dname).await;
-    let soa = create_soa_lookup_future(resolv.clone(), &dname).await;
+fn create_dname(name: &str) -> Option<Dname<Vec<u8>>> {
+    let mut name = idn::to_ascii(name)?;
+    if !name.ends_with('.') {
+        name.push('.')
+    }
+    Dname::<Vec<_>>::from_chars(name.chars()).ok()
+}
+
+async fn create_lookup_future(
+    handle: &DnsResolverHandle,
+    dname: &Dname<Vec<u8>>,
+) -> DnsLookupResult {
+    let a = create_a_lookup_future(handle, &dname).await;
+    let aaaa = create_aaaa_lookup_future(handle, &dname).await;
+    let cname = create_cname_lookup_future(handle, &dname).await;
+    let ns = create_ns_lookup_future(handle, &dname).await;
+    let mx = create_mx_lookup_future(handle, &dname).await;
+    let txt = create_txt_lookup_future(handle, &dname).await;
+    let soa = create_soa_lookup_future(handle, &dname).await;
     DnsLookupResult {
         a,
         aaaa,
@@ -73,8 +214,11 @@ async fn create_lookup_future(resolv: StubResolver, dname: &Dname<Vec<u8>>) -> D
     }
 }
 
-async fn create_a_lookup_future(resolv: StubResolver, dname: &Dname<Vec<u8>>) -> Vec<Ipv4Addr> {
-    match resolv.query((dname, Rtype::A, Class::In)).await {
+async fn create_a_lookup_future(
+    handle: &DnsResolverHandle,
+    dname: &Dname<Vec<u8>>,
+) -> Vec<Ipv4Addr> {
+    match handle.query(dname, Rtype::A).await {
         Ok(response) => {
             let mut addrs = Vec::new();
             for record in response.answer().unwrap().limit_to::<rdata::A>() {
@@ -88,8 +232,11 @@ async fn create_a_lookup_future(resolv: StubResolver, dname: &Dname<Vec<u8>>) ->
     }
 }
 
-async fn create_aaaa_lookup_future(resolv: StubResolver, dname: &Dname<Vec<u8>>) -> Vec<Ipv6Addr> {
-    match resolv.query((dname, Rtype::Aaaa, Class::In)).await {
+async fn create_aaaa_lookup_future(
+    handle: &DnsResolverHandle,
+    dname: &Dname<Vec<u8>>,
+) -> Vec<Ipv6Addr> {
+    match handle.query(dname, Rtype::Aaaa).await {
         Ok(response) => {
             let mut addrs = Vec::new();
             for record in response.answer().unwrap().limit_to::<rdata::Aaaa>() {
@@ -103,8 +250,11 @@ async fn create_aaaa_lookup_future(resolv: StubResolver, dname: &Dname<Vec<u8>>)
     }
 }
 
-async fn create_cname_lookup_future(resolv: StubResolver, dname: &Dname<Vec<u8>>) -> Vec<String> {
-    match resolv.query((dname, Rtype::Cname, Class::In)).await {
+async fn create_cname_lookup_future(
+    handle: &DnsResolverHandle,
+    dname: &Dname<Vec<u8>>,
+) -> Vec<String> {
+    match handle.query(dname, Rtype::Cname).await {
         Ok(response) => {
             let mut cnames = Vec::new();
             for record in response
@@ -123,8 +273,11 @@ async fn create_cname_lookup_future(resolv: StubResolver, dname: &Dname<Vec<u8>>
     }
 }
 
-async fn create_ns_lookup_future(resolv: StubResolver, dname: &Dname<Vec<u8>>) -> Vec<String> {
-    match resolv.query((dname, Rtype::Ns, Class::In)).await {
+async fn create_ns_lookup_future(
+    handle: &DnsResolverHandle,
+    dname: &Dname<Vec<u8>>,
+) -> Vec<String> {
+    match handle.query(dname, Rtype::Ns).await {
         Ok(response) => {
             let mut nss = Vec::new();
             for record in response
@@ -144,10 +297,10 @@ async fn create_ns_lookup_future(resolv: StubResolver, dname: &Dname<Vec<u8>>) -
 }
 
 async fn create_mx_lookup_future(
-    resolv: StubResolver,
+    handle: &DnsResolverHandle,
     dname: &Dname<Vec<u8>>,
 ) -> Vec<DnsLookupResultMx> {
-    match resolv.query((dname, Rtype::Mx, Class::In)).await {
+    match handle.query(dname, Rtype::Mx).await {
         Ok(response) => {
             let mut mxs = Vec::new();
             for record in response
@@ -169,8 +322,11 @@ async fn create_mx_lookup_future(
     }
 }
 
-async fn create_txt_lookup_future(resolv: StubResolver, dname: &Dname<Vec<u8>>) -> Vec<String> {
-    match resolv.query((dname, Rtype::Txt, Class::In)).await {
+async fn create_txt_lookup_future(
+    handle: &DnsResolverHandle,
+    dname: &Dname<Vec<u8>>,
+) -> Vec<String> {
+    match handle.query(dname, Rtype::Txt).await {
         Ok(response) => {
             let mut txts = Vec::new();
             for record in response.answer().unwrap().limit_to::<rdata::Txt<_>>() {
@@ -185,11 +341,35 @@ async fn create_txt_lookup_future(resolv: StubResolver, dname: &Dname<Vec<u8>>)
     }
 }
 
+// The lowest ttl of the answer records. An empty answer may be cached for the negative
+// caching ttl of the zone's SOA in the authority section (RFC 2308), or not at all when
+// there is none.
+fn answer_ttl(answer: &Answer) -> u32 {
+    let min_ttl = answer
+        .answer()
+        .ok()
+        .and_then(|records| records.flatten().map(|record| record.ttl()).min());
+    if let Some(ttl) = min_ttl {
+        return ttl;
+    }
+    answer
+        .authority()
+        .ok()
+        .and_then(|records| {
+            records
+                .limit_to::<rdata::Soa<ParsedDname<_>>>()
+                .flatten()
+                .next()
+        })
+        .map(|record| record.ttl().min(record.data().minimum()))
+        .unwrap_or(0)
+}
+
 async fn create_soa_lookup_future(
-    resolv: StubResolver,
+    handle: &DnsResolverHandle,
     dname: &Dname<Vec<u8>>,
 ) -> Option<DnsLookupResultSoa> {
-    match resolv.query((dname, Rtype::Soa, Class::In)).await {
+    match handle.query(dname, Rtype::Soa).await {
         Ok(response) => response
             .answer()
             .unwrap()
@@ -244,6 +424,35 @@ impl DnsLookupResult {
             soa: None,
         }
     }
+
+    pub fn addresses(&self) -> DnsAddressLookupResult {
+        DnsAddressLookupResult {
+            a: self.a.clone(),
+            aaaa: self.aaaa.clone(),
+            cname: self.cname.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsAddressLookupResult {
+    pub a: Vec<Ipv4Addr>,
+    pub aaaa: Vec<Ipv6Addr>,
+    pub cname: Vec<String>,
+}
+
+impl DnsAddressLookupResult {
+    pub fn empty() -> DnsAddressLookupResult {
+        DnsAddressLookupResult {
+            a: Vec::new(),
+            aaaa: Vec::new(),
+            cname: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.a.is_empty() && self.aaaa.is_empty() && self.cname.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
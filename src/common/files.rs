@@ -5,6 +5,9 @@ static DEFAULT_ASN_FILE_NAME: &'static str = "asn.dat";
 static DEFAULT_IP2ASN_FILE_NAME: &'static str = "ip2asn.dat";
 static DEFAULT_ENCODED_IP2ASN_FILE_NAME: &'static str = "ip2asn-encoded.dat";
 static DEFAULT_MAXMIND_FILE_NAME: &'static str = "maxmind-geolite2-city.mmdb";
+static DEFAULT_MAXMIND_ASN_FILE_NAME: &'static str = "maxmind-geolite2-asn.mmdb";
+static DEFAULT_MAXMIND_COUNTRY_FILE_NAME: &'static str = "maxmind-geolite2-country.mmdb";
+static DEFAULT_PUBLIC_SUFFIX_LIST_FILE_NAME: &'static str = "public_suffix_list.dat";
 
 pub fn get_default_directory() -> PathBuf {
     dirs::home_dir().unwrap().join(".local/share/net-lookup")
@@ -34,6 +37,30 @@ pub fn get_default_maxmind_path() -> PathBuf {
     get_maxmind_path(&get_default_directory())
 }
 
+pub fn get_maxmind_asn_path(directory: &Path) -> PathBuf {
+    directory.join(DEFAULT_MAXMIND_ASN_FILE_NAME)
+}
+
+pub fn get_default_maxmind_asn_path() -> PathBuf {
+    get_maxmind_asn_path(&get_default_directory())
+}
+
+pub fn get_maxmind_country_path(directory: &Path) -> PathBuf {
+    directory.join(DEFAULT_MAXMIND_COUNTRY_FILE_NAME)
+}
+
+pub fn get_default_maxmind_country_path() -> PathBuf {
+    get_maxmind_country_path(&get_default_directory())
+}
+
+pub fn get_public_suffix_list_path(directory: &Path) -> PathBuf {
+    directory.join(DEFAULT_PUBLIC_SUFFIX_LIST_FILE_NAME)
+}
+
+pub fn get_default_public_suffix_list_path() -> PathBuf {
+    get_public_suffix_list_path(&get_default_directory())
+}
+
 pub fn get_encoded_ip2asn_path(directory: &Path) -> PathBuf {
     directory.join(DEFAULT_ENCODED_IP2ASN_FILE_NAME)
 }
@@ -0,0 +1,518 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::batch::BatchLookupResult;
+use crate::geo::DistanceResult;
+use crate::lookup::{
+    AsnLookupResult, DomainLookupResult, HostLookupResult, IpLookupResult, LookupResult,
+    PrefixLookupResult, RoutedPrefix,
+};
+use crate::maxmind;
+use crate::projection::Projection;
+
+static RECORD_COLUMNS: &'static [&'static str] = &[
+    "query", "ip", "prefix", "asn", "as_name", "country", "city", "lat", "lon", "ptr",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Json,
+    PrettyJson,
+    Ndjson,
+    Yaml,
+    Csv,
+    Tsv,
+    Table,
+}
+
+impl OutputFormat {
+    pub fn names() -> &'static [&'static str] {
+        &["json", "pretty", "ndjson", "yaml", "csv", "tsv", "table"]
+    }
+
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "json" => Some(OutputFormat::Json),
+            "pretty" => Some(OutputFormat::PrettyJson),
+            "ndjson" => Some(OutputFormat::Ndjson),
+            "yaml" => Some(OutputFormat::Yaml),
+            "csv" => Some(OutputFormat::Csv),
+            "tsv" => Some(OutputFormat::Tsv),
+            "table" => Some(OutputFormat::Table),
+            _ => None,
+        }
+    }
+
+    pub fn from_accept(accept: &str) -> Option<OutputFormat> {
+        accept
+            .split(',')
+            .map(|media| media.split(';').next().unwrap_or("").trim())
+            .find_map(|media| match media {
+                "application/json" => Some(OutputFormat::Json),
+                "application/x-ndjson" | "application/jsonlines" => Some(OutputFormat::Ndjson),
+                "application/yaml" | "application/x-yaml" | "text/yaml" => Some(OutputFormat::Yaml),
+                "text/csv" => Some(OutputFormat::Csv),
+                "text/tab-separated-values" => Some(OutputFormat::Tsv),
+                "text/plain" => Some(OutputFormat::Table),
+                _ => None,
+            })
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Json | OutputFormat::PrettyJson => "application/json",
+            OutputFormat::Ndjson => "application/x-ndjson",
+            OutputFormat::Yaml => "application/yaml",
+            OutputFormat::Csv => "text/csv",
+            OutputFormat::Tsv => "text/tab-separated-values",
+            OutputFormat::Table => "text/plain; charset=utf-8",
+        }
+    }
+
+    // Csv and tsv rows are flat, fields that aren't one of their columns can't be selected.
+    pub fn check_fields(&self, projection: &Projection) -> Result<(), String> {
+        if !matches!(self, OutputFormat::Csv | OutputFormat::Tsv) {
+            return Ok(());
+        }
+        let unknown: Vec<&str> = projection
+            .columns()
+            .iter()
+            .map(|c| c.as_str())
+            .filter(|c| !RECORD_COLUMNS.contains(c))
+            .collect();
+        if unknown.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "unsupported csv fields: {}, expected any of {}",
+                unknown.join(", "),
+                RECORD_COLUMNS.join(", ")
+            ))
+        }
+    }
+
+    pub fn header(&self, projection: &Projection) -> Option<String> {
+        match self {
+            OutputFormat::Csv | OutputFormat::Tsv => {
+                let columns = selected_columns(projection);
+                Some(self.write_rows(&[columns.iter().map(|c| c.to_string()).collect()]))
+            }
+            _ => None,
+        }
+    }
+
+    fn write_rows(&self, rows: &[Vec<String>]) -> String {
+        let delimiter = if *self == OutputFormat::Tsv {
+            b'\t'
+        } else {
+            b','
+        };
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(false)
+            .from_writer(Vec::new());
+        for row in rows {
+            writer.write_record(row).unwrap();
+        }
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+}
+
+pub fn format_document<T: Formattable>(
+    item: &T,
+    format: OutputFormat,
+    projection: &Projection,
+) -> String {
+    format.header(projection).unwrap_or_default() + &format_item(item, format, projection)
+}
+
+pub fn format_item<T: Formattable>(
+    item: &T,
+    format: OutputFormat,
+    projection: &Projection,
+) -> String {
+    match format {
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            serde_json::to_string(&item.to_projected_value(projection)).unwrap() + "\n"
+        }
+        OutputFormat::PrettyJson => {
+            serde_json::to_string_pretty(&item.to_projected_value(projection)).unwrap() + "\n"
+        }
+        OutputFormat::Yaml => serde_yaml::to_string(&item.to_projected_value(projection)).unwrap(),
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            let columns = selected_columns(projection);
+            let rows: Vec<Vec<String>> = item
+                .to_records(projection.languages())
+                .iter()
+                .map(|r| columns.iter().map(|c| r.column(c)).collect())
+                .collect();
+            format.write_rows(&rows)
+        }
+        OutputFormat::Table => {
+            let mut out = String::new();
+            render_tree(&item.to_projected_value(projection), 0, &mut out);
+            out + "\n"
+        }
+    }
+}
+
+pub fn format_list<T: Formattable>(
+    items: &[T],
+    format: OutputFormat,
+    projection: &Projection,
+) -> String {
+    match format {
+        OutputFormat::Json | OutputFormat::PrettyJson | OutputFormat::Yaml => {
+            let values: Vec<Value> = items
+                .iter()
+                .map(|i| i.to_projected_value(projection))
+                .collect();
+            match format {
+                OutputFormat::Json => serde_json::to_string(&values).unwrap() + "\n",
+                OutputFormat::PrettyJson => serde_json::to_string_pretty(&values).unwrap() + "\n",
+                _ => serde_yaml::to_string(&values).unwrap(),
+            }
+        }
+        _ => {
+            let body: String = items
+                .iter()
+                .map(|i| format_item(i, format, projection))
+                .collect();
+            format.header(projection).unwrap_or_default() + &body
+        }
+    }
+}
+
+fn selected_columns(projection: &Projection) -> Vec<&'static str> {
+    let columns: Vec<&'static str> = projection
+        .columns()
+        .iter()
+        .filter_map(|c| RECORD_COLUMNS.iter().find(|rc| *rc == c).copied())
+        .collect();
+    if columns.is_empty() {
+        RECORD_COLUMNS.to_vec()
+    } else {
+        columns
+    }
+}
+
+fn render_tree(value: &Value, indent: usize, out: &mut String) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter().filter(|(_, v)| !is_blank(v)) {
+                match value {
+                    Value::Object(_) | Value::Array(_) => {
+                        out.push_str(&format!("{}{}:\n", pad, key));
+                        render_tree(value, indent + 2, out);
+                    }
+                    _ => out.push_str(&format!("{}{}: {}\n", pad, key, scalar(value))),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values.iter().filter(|v| !is_blank(v)) {
+                match value {
+                    Value::Object(_) | Value::Array(_) => {
+                        out.push_str(&format!("{}-\n", pad));
+                        render_tree(value, indent + 2, out);
+                    }
+                    _ => out.push_str(&format!("{}- {}\n", pad, scalar(value))),
+                }
+            }
+        }
+        _ => out.push_str(&format!("{}{}\n", pad, scalar(value))),
+    }
+}
+
+fn is_blank(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Array(values) => values.is_empty(),
+        Value::Object(map) => map.is_empty(),
+        _ => false,
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FlatRecord {
+    pub query: String,
+    pub ip: String,
+    pub prefix: String,
+    pub asn: String,
+    pub as_name: String,
+    pub country: String,
+    pub city: String,
+    pub lat: String,
+    pub lon: String,
+    pub ptr: String,
+}
+
+impl FlatRecord {
+    fn from_ip(query: &str, result: &IpLookupResult, languages: &[String]) -> FlatRecord {
+        let geo = result.geo();
+        let location = geo.and_then(|g| g.location.as_ref());
+        let asn = result.asn();
+
+        FlatRecord {
+            query: query.to_owned(),
+            ip: result.ip().to_string(),
+            prefix: display(result.prefix()),
+            asn: display(asn.map(|a| a.id())),
+            as_name: display(asn.map(|a| a.name().unwrap_or(a.handle()))),
+            country: display(
+                geo.and_then(|g| g.country.as_ref())
+                    .and_then(|c| c.iso_code.clone()),
+            ),
+            city: display(
+                geo.and_then(|g| g.city.as_ref())
+                    .and_then(|c| maxmind::localized_name(c.names.as_ref(), languages)),
+            ),
+            lat: display(location.and_then(|l| l.latitude)),
+            lon: display(location.and_then(|l| l.longitude)),
+            ptr: result.reverse_dns().join(" "),
+        }
+    }
+
+    fn from_prefix(query: &str, routed: &RoutedPrefix) -> FlatRecord {
+        let asn = routed.asn.as_ref();
+        FlatRecord {
+            query: query.to_owned(),
+            prefix: routed.prefix.to_string(),
+            asn: routed.asn_id.to_string(),
+            as_name: display(asn.map(|a| a.name().unwrap_or(a.handle()))),
+            country: display(asn.map(|a| a.country())),
+            ..FlatRecord::default()
+        }
+    }
+
+    fn column(&self, name: &str) -> String {
+        match name {
+            "query" => self.query.clone(),
+            "ip" => self.ip.clone(),
+            "prefix" => self.prefix.clone(),
+            "asn" => self.asn.clone(),
+            "as_name" => self.as_name.clone(),
+            "country" => self.country.clone(),
+            "city" => self.city.clone(),
+            "lat" => self.lat.clone(),
+            "lon" => self.lon.clone(),
+            "ptr" => self.ptr.clone(),
+            _ => String::new(),
+        }
+    }
+}
+
+fn display<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+pub trait Formattable: Serialize {
+    fn to_records(&self, languages: &[String]) -> Vec<FlatRecord>;
+
+    fn to_projected_value(&self, projection: &Projection) -> Value {
+        projection.apply(serde_json::to_value(self).unwrap())
+    }
+}
+
+impl Formattable for IpLookupResult {
+    fn to_records(&self, languages: &[String]) -> Vec<FlatRecord> {
+        vec![FlatRecord::from_ip(&self.ip().to_string(), self, languages)]
+    }
+}
+
+impl Formattable for DomainLookupResult {
+    fn to_records(&self, languages: &[String]) -> Vec<FlatRecord> {
+        let records: Vec<FlatRecord> = self
+            .ipv4
+            .iter()
+            .chain(self.ipv6.iter())
+            .map(|ip| FlatRecord::from_ip(&self.domain, ip, languages))
+            .collect();
+        if records.is_empty() {
+            vec![FlatRecord {
+                query: self.domain.clone(),
+                ..FlatRecord::default()
+            }]
+        } else {
+            records
+        }
+    }
+}
+
+impl Formattable for HostLookupResult {
+    fn to_records(&self, languages: &[String]) -> Vec<FlatRecord> {
+        self.ips
+            .iter()
+            .map(|ip| FlatRecord::from_ip(&self.host, ip, languages))
+            .collect()
+    }
+}
+
+impl Formattable for LookupResult {
+    fn to_records(&self, languages: &[String]) -> Vec<FlatRecord> {
+        match self {
+            LookupResult::Ip(result) => result.to_records(languages),
+            LookupResult::Domain(result) => result.to_records(languages),
+        }
+    }
+}
+
+impl Formattable for AsnLookupResult {
+    fn to_records(&self, _languages: &[String]) -> Vec<FlatRecord> {
+        let asn = self.asn.as_ref();
+        let record = FlatRecord {
+            query: self.id.to_string(),
+            asn: self.id.to_string(),
+            as_name: display(asn.map(|a| a.name().unwrap_or(a.handle()))),
+            country: display(asn.map(|a| a.country())),
+            ..FlatRecord::default()
+        };
+        if self.prefixes.is_empty() {
+            return vec![record];
+        }
+        self.prefixes
+            .iter()
+            .map(|prefix| FlatRecord {
+                prefix: prefix.to_string(),
+                ..record.clone()
+            })
+            .collect()
+    }
+}
+
+impl Formattable for PrefixLookupResult {
+    fn to_records(&self, _languages: &[String]) -> Vec<FlatRecord> {
+        let query = self.prefix.to_string();
+        self.covering
+            .iter()
+            .chain(self.more_specifics.iter())
+            .map(|routed| FlatRecord::from_prefix(&query, routed))
+            .collect()
+    }
+}
+
+impl Formattable for DistanceResult {
+    fn to_records(&self, languages: &[String]) -> Vec<FlatRecord> {
+        vec![
+            FlatRecord::from_ip(&self.from.ip().to_string(), &self.from, languages),
+            FlatRecord::from_ip(&self.to.ip().to_string(), &self.to, languages),
+        ]
+    }
+}
+
+impl Formattable for BatchLookupResult {
+    fn to_records(&self, languages: &[String]) -> Vec<FlatRecord> {
+        match &self.result {
+            Some(result) => result.to_records(languages),
+            None => vec![FlatRecord {
+                query: self
+                    .query
+                    .as_str()
+                    .map(|q| q.to_owned())
+                    .unwrap_or_else(|| self.query.to_string()),
+                ..FlatRecord::default()
+            }],
+        }
+    }
+
+    fn to_projected_value(&self, projection: &Projection) -> Value {
+        let mut value = serde_json::to_value(self).unwrap();
+        if let Some(result) = value.get_mut("result") {
+            *result = projection.apply(result.take());
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cidr::AnyIpCidr;
+
+    use super::*;
+    use crate::projection::GeoView;
+
+    fn projection(fields: Option<&str>) -> Projection {
+        Projection::new(fields, GeoView::Compact)
+    }
+
+    fn failed(query: &str) -> BatchLookupResult {
+        BatchLookupResult {
+            query: Value::String(query.to_owned()),
+            result: None,
+            error: Some("invalid query".to_owned()),
+            distance_km: None,
+        }
+    }
+
+    #[test]
+    fn csv_escapes_delimiters_and_quotes() {
+        let item = failed("a,b \"c\"");
+        let csv = format_item(&item, OutputFormat::Csv, &projection(Some("query,ip")));
+        assert_eq!(csv, "\"a,b \"\"c\"\"\",\n");
+    }
+
+    #[test]
+    fn tsv_keeps_commas_and_escapes_tabs() {
+        let tsv = format_item(
+            &failed("a,b"),
+            OutputFormat::Tsv,
+            &projection(Some("query,ip")),
+        );
+        assert_eq!(tsv, "a,b\t\n");
+        let tsv = format_item(
+            &failed("a\tb"),
+            OutputFormat::Tsv,
+            &projection(Some("query")),
+        );
+        assert_eq!(tsv, "\"a\tb\"\n");
+    }
+
+    #[test]
+    fn check_fields_rejects_nested_fields_for_flat_formats() {
+        let nested = projection(Some("ip,geo.city"));
+        assert!(OutputFormat::Csv
+            .check_fields(&projection(Some("ip,asn")))
+            .is_ok());
+        assert!(OutputFormat::Csv.check_fields(&projection(None)).is_ok());
+        assert!(OutputFormat::Json.check_fields(&nested).is_ok());
+        let err = OutputFormat::Tsv.check_fields(&nested).unwrap_err();
+        assert!(
+            err.starts_with("unsupported csv fields: geo.city"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn batch_csv_keeps_a_row_per_item() {
+        let prefixes = vec!["192.0.2.0/24".parse::<AnyIpCidr>().unwrap()];
+        let items = vec![
+            failed("not an ip"),
+            BatchLookupResult {
+                query: Value::from(64496),
+                result: None,
+                error: Some("unknown asn".to_owned()),
+                distance_km: None,
+            },
+            failed("example.invalid"),
+        ];
+        let csv = format_list(&items, OutputFormat::Csv, &projection(Some("query,asn")));
+        assert_eq!(csv, "query,asn\nnot an ip,\n64496,\nexample.invalid,\n");
+
+        let asn = AsnLookupResult {
+            id: 64496,
+            asn: None,
+            prefixes,
+        };
+        let csv = format_document(&asn, OutputFormat::Csv, &projection(Some("asn,prefix")));
+        assert_eq!(csv, "asn,prefix\n64496,192.0.2.0/24\n");
+    }
+}
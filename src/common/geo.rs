@@ -0,0 +1,98 @@
+use crate::lookup::{IpLookupResult, LookupResult};
+
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct GeoPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl GeoPoint {
+    pub fn parse(value: &str) -> Result<GeoPoint, String> {
+        let coordinates: Vec<f64> = value
+            .split(',')
+            .map(|c| c.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("'{}' is not a valid lat,lon pair", value))?;
+        match coordinates.as_slice() {
+            [latitude, longitude] if latitude.abs() <= 90.0 && longitude.abs() <= 180.0 => {
+                Ok(GeoPoint {
+                    latitude: *latitude,
+                    longitude: *longitude,
+                })
+            }
+            _ => Err(format!("'{}' is not a valid lat,lon pair", value)),
+        }
+    }
+
+    pub fn of(result: &IpLookupResult) -> Option<GeoPoint> {
+        let location = result.geo()?.location.as_ref()?;
+        Some(GeoPoint {
+            latitude: location.latitude?,
+            longitude: location.longitude?,
+        })
+    }
+
+    pub fn distance_km(&self, other: &GeoPoint) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.longitude - self.longitude).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+    }
+}
+
+fn accuracy_radius_km(result: &IpLookupResult) -> Option<u16> {
+    result
+        .geo()
+        .and_then(|g| g.location.as_ref())
+        .and_then(|l| l.accuracy_radius)
+}
+
+pub fn create_distance_result(from: IpLookupResult, to: IpLookupResult) -> DistanceResult {
+    let distance_km = match (GeoPoint::of(&from), GeoPoint::of(&to)) {
+        (Some(a), Some(b)) => Some(a.distance_km(&b)),
+        _ => None,
+    };
+    let from_accuracy_radius_km = accuracy_radius_km(&from);
+    let to_accuracy_radius_km = accuracy_radius_km(&to);
+    let uncertainty_km =
+        from_accuracy_radius_km.unwrap_or(0) as f64 + to_accuracy_radius_km.unwrap_or(0) as f64;
+
+    DistanceResult {
+        distance_km,
+        min_distance_km: distance_km.map(|d| (d - uncertainty_km).max(0.0)),
+        max_distance_km: distance_km.map(|d| d + uncertainty_km),
+        from_accuracy_radius_km,
+        to_accuracy_radius_km,
+        from,
+        to,
+    }
+}
+
+// The closest located address of a lookup result, so a domain counts as near
+// when any of its addresses is.
+pub fn nearest_distance_km(result: &LookupResult, point: &GeoPoint) -> Option<f64> {
+    let ips: Vec<&IpLookupResult> = match result {
+        LookupResult::Ip(ip) => vec![ip],
+        LookupResult::Domain(domain) => domain.ipv4.iter().chain(domain.ipv6.iter()).collect(),
+    };
+    ips.into_iter()
+        .filter_map(GeoPoint::of)
+        .map(|p| p.distance_km(point))
+        .fold(None, |min: Option<f64>, d| {
+            Some(min.map_or(d, |m| m.min(d)))
+        })
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DistanceResult {
+    pub distance_km: Option<f64>,
+    pub min_distance_km: Option<f64>,
+    pub max_distance_km: Option<f64>,
+    pub from_accuracy_radius_km: Option<u16>,
+    pub to_accuracy_radius_km: Option<u16>,
+    pub from: IpLookupResult,
+    pub to: IpLookupResult,
+}
@@ -0,0 +1,205 @@
+use std::collections::BTreeSet;
+
+use idna;
+
+pub fn analyze_domain(domain: &str) -> IdnDomain {
+    let trimmed = domain.trim_end_matches('.');
+    let ascii = to_ascii(trimmed).unwrap_or_else(|| trimmed.to_lowercase());
+    let (unicode, _) = idna::domain_to_unicode(&ascii);
+    let warnings = unicode
+        .split('.')
+        .filter(|label| !label.is_ascii())
+        .filter_map(analyze_label)
+        .collect();
+
+    IdnDomain {
+        ascii,
+        unicode,
+        warnings,
+    }
+}
+
+pub fn to_ascii(domain: &str) -> Option<String> {
+    idna::domain_to_ascii(domain).ok()
+}
+
+fn analyze_label(label: &str) -> Option<IdnLabelWarning> {
+    let scripts: BTreeSet<&'static str> = label
+        .chars()
+        .map(script_of)
+        .filter(|s| *s != COMMON)
+        .collect();
+    let skeleton: String = label.chars().map(latin_skeleton).collect();
+
+    let mixed_script = !is_allowed_script_set(&scripts);
+    let confusable = skeleton != label && skeleton.is_ascii();
+
+    if mixed_script || confusable {
+        Some(IdnLabelWarning {
+            label: label.to_owned(),
+            scripts: scripts.into_iter().collect(),
+            mixed_script,
+            confusable,
+            skeleton: if confusable { Some(skeleton) } else { None },
+        })
+    } else {
+        None
+    }
+}
+
+// Follows the "highly restrictive" profile of UTS #39: a single script, or one of the
+// customary combinations of Latin with Han, Hiragana, Katakana and Hangul.
+fn is_allowed_script_set(scripts: &BTreeSet<&'static str>) -> bool {
+    if scripts.len() <= 1 {
+        return true;
+    }
+    let allowed: &[&[&str]] = &[
+        &["latin", "han", "hiragana", "katakana"],
+        &["latin", "han", "bopomofo"],
+        &["latin", "han", "hangul"],
+    ];
+    allowed
+        .iter()
+        .any(|set| scripts.iter().all(|s| set.contains(s)))
+}
+
+const COMMON: &str = "common";
+
+fn script_of(c: char) -> &'static str {
+    match c as u32 {
+        0x0030..=0x0039 | 0x002D | 0x00B7 | 0x30FC => COMMON,
+        0x0041..=0x005A | 0x0061..=0x007A | 0x00C0..=0x024F | 0x1E00..=0x1EFF => "latin",
+        0x0370..=0x03FF | 0x1F00..=0x1FFF => "greek",
+        0x0400..=0x052F | 0x2DE0..=0x2DFF | 0xA640..=0xA69F => "cyrillic",
+        0x0530..=0x058F => "armenian",
+        0x0590..=0x05FF => "hebrew",
+        0x0600..=0x06FF | 0x0750..=0x077F | 0x08A0..=0x08FF => "arabic",
+        0x0900..=0x097F => "devanagari",
+        0x0E00..=0x0E7F => "thai",
+        0x10A0..=0x10FF => "georgian",
+        0x3040..=0x309F => "hiragana",
+        0x30A0..=0x30FF | 0x31F0..=0x31FF => "katakana",
+        0x3100..=0x312F => "bopomofo",
+        0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => "hangul",
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FA1F => "han",
+        _ => "other",
+    }
+}
+
+// Maps characters that are commonly mistaken for latin letters onto the letter they
+// imitate. Every entry is taken from the UTS #39 confusables.txt data where the prototype
+// is a single lowercase latin letter, limited to the cyrillic, greek, armenian and latin
+// homoglyphs most often seen in phishing domains.
+fn latin_skeleton(c: char) -> char {
+    match c {
+        'а' | 'ɑ' | 'α' => 'a',
+        'с' | 'ϲ' | 'ᴄ' => 'c',
+        'ԁ' => 'd',
+        'е' | 'ҽ' => 'e',
+        'ɡ' | 'ց' => 'g',
+        'һ' | 'հ' => 'h',
+        'і' | 'ι' | 'ı' | 'ɩ' => 'i',
+        'ј' | 'ϳ' => 'j',
+        'ӏ' => 'l',
+        'ո' | 'ռ' => 'n',
+        'о' | 'ο' | 'σ' | 'օ' => 'o',
+        'р' | 'ρ' => 'p',
+        'ԛ' | 'զ' => 'q',
+        'ѕ' => 's',
+        'ս' => 'u',
+        'ν' | 'ѵ' => 'v',
+        'ԝ' | 'ѡ' => 'w',
+        'х' => 'x',
+        'у' | 'γ' | 'ү' => 'y',
+        _ => c,
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct IdnDomain {
+    pub ascii: String,
+    pub unicode: String,
+    pub warnings: Vec<IdnLabelWarning>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct IdnLabelWarning {
+    pub label: String,
+    pub scripts: Vec<&'static str>,
+    pub mixed_script: bool,
+    pub confusable: bool,
+    pub skeleton: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_domains_have_no_warnings() {
+        let domain = analyze_domain("Example.COM.");
+        assert_eq!(domain.ascii, "example.com");
+        assert_eq!(domain.unicode, "example.com");
+        assert!(domain.warnings.is_empty());
+    }
+
+    #[test]
+    fn punycode_is_decoded() {
+        let domain = analyze_domain("xn--bcher-kva.de");
+        assert_eq!(domain.ascii, "xn--bcher-kva.de");
+        assert_eq!(domain.unicode, "bücher.de");
+        assert!(domain.warnings.is_empty());
+    }
+
+    #[test]
+    fn whole_script_confusable() {
+        // "аррӏе" is written entirely in cyrillic.
+        let domain = analyze_domain("xn--80ak6aa92e.com");
+        assert_eq!(domain.unicode, "аррӏе.com");
+        assert_eq!(domain.warnings.len(), 1);
+        let warning = &domain.warnings[0];
+        assert_eq!(warning.scripts, vec!["cyrillic"]);
+        assert!(!warning.mixed_script);
+        assert!(warning.confusable);
+        assert_eq!(warning.skeleton.as_deref(), Some("apple"));
+    }
+
+    #[test]
+    fn mixed_script_confusable() {
+        let domain = analyze_domain("pаypal.com");
+        assert_eq!(domain.warnings.len(), 1);
+        let warning = &domain.warnings[0];
+        assert_eq!(warning.label, "pаypal");
+        assert_eq!(warning.scripts, vec!["cyrillic", "latin"]);
+        assert!(warning.mixed_script);
+        assert_eq!(warning.skeleton.as_deref(), Some("paypal"));
+    }
+
+    #[test]
+    fn letters_outside_the_confusables_table_are_kept() {
+        // Neither 'п' nor 'г' is confusable with a latin letter in UTS #39.
+        let domain = analyze_domain("пример.рф");
+        assert!(domain.warnings.is_empty());
+        let domain = analyze_domain("гоо.com");
+        assert!(domain.warnings.is_empty());
+    }
+
+    #[test]
+    fn customary_script_combinations_are_allowed() {
+        let domain = analyze_domain("abc日本語ひらがな.jp");
+        assert!(domain.warnings.is_empty());
+        let domain = analyze_domain("abcгоо.com");
+        assert_eq!(domain.warnings.len(), 1);
+        assert!(domain.warnings[0].mixed_script);
+        assert!(!domain.warnings[0].confusable);
+    }
+
+    #[test]
+    fn to_ascii_encodes_and_lowercases() {
+        assert_eq!(to_ascii("bücher.de").as_deref(), Some("xn--bcher-kva.de"));
+        assert_eq!(to_ascii("EXAMPLE.com").as_deref(), Some("example.com"));
+        assert_eq!(to_ascii("аррӏе.com").as_deref(), Some("xn--80ak6aa92e.com"));
+        // Not valid punycode.
+        assert_eq!(to_ascii("xn--a.com"), None);
+    }
+}
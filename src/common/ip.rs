@@ -1,5 +1,5 @@
-use std::collections::BTreeMap;
 use std::collections::Bound::{Included, Unbounded};
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::{BufRead, BufReader, Result};
 use std::net::IpAddr;
@@ -7,11 +7,17 @@ use std::path::Path;
 use std::sync::Arc;
 
 use crate::asn::{AsnDatabase, AutonomousSystemNumber};
+use crate::logging;
+use crate::status::{self, DataFileInfo};
 use cidr::AnyIpCidr;
 
-pub fn load_ip_asn_database(file_path: &Path, asn_database: &AsnDatabase) -> Result<IpAsnDatabase> {
+pub fn load_ip_asn_database(
+    file_path: &Path,
+    asn_database: Option<&AsnDatabase>,
+) -> Result<IpAsnDatabase> {
     let file = File::open(file_path)?;
     let mut ip_map = BTreeMap::new();
+    let mut rib_timestamp = None;
 
     for line_res in BufReader::new(file).lines() {
         if line_res.is_ok() {
@@ -19,15 +25,70 @@ pub fn load_ip_asn_database(file_path: &Path, asn_database: &AsnDatabase) -> Res
             if !line.starts_with(';') {
                 let ip_block = parse_ip_block(line, asn_database);
                 ip_map.insert(ip_block.start, ip_block);
+            } else if rib_timestamp.is_none() {
+                rib_timestamp = parse_rib_timestamp(&line);
             }
         } else {
-            println!("[IP] skipping non utf8");
+            logging::warn("skipping non utf8 line", &[("file", &file_path.display())]);
         }
     }
-    Ok(IpAsnDatabase { ip_asn_map: ip_map })
+    let mut info = status::create_data_file_info("ip2asn", file_path);
+    info.records = Some(ip_map.len());
+    info.build_epoch = rib_timestamp.as_ref().map(|(_, epoch)| *epoch);
+    info.rib_timestamp = rib_timestamp.map(|(timestamp, _)| timestamp);
+
+    // Prefix starts per asn, in address order, so listing an asn's prefixes doesn't scan
+    // the whole table.
+    let mut asn_prefixes: HashMap<u32, Vec<IpAddr>> = HashMap::new();
+    for record in ip_map.values() {
+        asn_prefixes
+            .entry(record.asn_id)
+            .or_default()
+            .push(record.start);
+    }
+    Ok(IpAsnDatabase {
+        ip_asn_map: ip_map,
+        asn_prefixes,
+        info,
+    })
+}
+
+// pyasn records the RIB dump it was converted from in a header comment such as
+// "; Original source: rib.20220502.0600.bz2".
+fn parse_rib_timestamp(line: &str) -> Option<(String, u64)> {
+    let source = line.split("rib.").nth(1)?;
+    let mut parts = source.split('.');
+    let date = parts.next().filter(|d| d.len() == 8)?;
+    let time = parts.next().filter(|t| t.len() == 4)?;
+
+    let year = date[0..4].parse::<i64>().ok()?;
+    let month = date[4..6].parse::<i64>().ok()?;
+    let day = date[6..8].parse::<i64>().ok()?;
+    let hour = time[0..2].parse::<u64>().ok()?;
+    let minute = time[2..4].parse::<u64>().ok()?;
+
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return None;
+    }
+    let epoch = days as u64 * 86400 + hour * 3600 + minute * 60;
+    let timestamp = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:00Z",
+        year, month, day, hour, minute
+    );
+    Some((timestamp, epoch))
 }
 
-fn parse_ip_block(line: String, asn_database: &AsnDatabase) -> IpAsnRecord {
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn parse_ip_block(line: String, asn_database: Option<&AsnDatabase>) -> IpAsnRecord {
     let cidr_slash_idx = line.find('/').unwrap();
     let tab_idx = line.find('\t').unwrap();
 
@@ -48,7 +109,11 @@ fn parse_ip_block(line: String, asn_database: &AsnDatabase) -> IpAsnRecord {
     IpAsnRecord {
         start: cidr.first_address().unwrap(),
         end: cidr.last_address().unwrap(),
-        asn: asn_database.lookup(asn_id).map(|r| r.clone()),
+        prefix: cidr,
+        asn_id,
+        asn: asn_database
+            .and_then(|db| db.lookup(asn_id))
+            .map(|r| r.clone()),
     }
 }
 
@@ -56,17 +121,45 @@ fn parse_ip_block(line: String, asn_database: &AsnDatabase) -> IpAsnRecord {
 pub struct IpAsnRecord {
     pub start: IpAddr,
     pub end: IpAddr,
+    pub prefix: AnyIpCidr,
+    pub asn_id: u32,
     pub asn: Option<Arc<AutonomousSystemNumber>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct IpAsnDatabase {
     ip_asn_map: BTreeMap<IpAddr, IpAsnRecord>,
+    asn_prefixes: HashMap<u32, Vec<IpAddr>>,
+    info: DataFileInfo,
 }
 
 impl IpAsnDatabase {
+    pub fn info(&self) -> &DataFileInfo {
+        &self.info
+    }
+
     pub fn lookup(&self, ip: IpAddr) -> Option<&IpAsnRecord> {
         let range = self.ip_asn_map.range((Unbounded, Included(ip)));
         range.last().map(|e| e.1)
     }
+
+    pub fn lookup_range(&self, start: IpAddr, end: IpAddr) -> Vec<&IpAsnRecord> {
+        self.ip_asn_map
+            .range((Included(start), Included(end)))
+            .map(|e| e.1)
+            .filter(|r| r.end <= end)
+            .collect()
+    }
+
+    pub fn lookup_asn_prefixes(&self, asn_id: u32) -> Vec<&IpAsnRecord> {
+        self.asn_prefixes
+            .get(&asn_id)
+            .map(|starts| {
+                starts
+                    .iter()
+                    .filter_map(|start| self.ip_asn_map.get(start))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 }
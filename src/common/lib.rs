@@ -3,20 +3,43 @@ extern crate serde_derive;
 
 extern crate bincode;
 extern crate cidr;
+extern crate csv;
 extern crate dirs;
 extern crate domain;
 extern crate futures;
 extern crate hyper;
+extern crate idna;
 extern crate maxminddb;
+extern crate rustls_pemfile;
 extern crate serde;
 extern crate serde_json;
+extern crate serde_yaml;
 extern crate tokio;
 extern crate tokio_core;
+extern crate tokio_rustls;
 
+pub mod access;
 pub mod asn;
+pub mod batch;
+pub mod cors;
+pub mod data;
 pub mod dns;
 pub mod files;
+pub mod format;
+pub mod geo;
+pub mod idn;
 pub mod ip;
+pub mod listener;
+pub mod logging;
 pub mod lookup;
 pub mod maxmind;
+pub mod metrics;
+pub mod projection;
+pub mod proxy;
+pub mod psl;
+pub mod reload;
 pub mod service;
+pub mod shutdown;
+pub mod status;
+pub mod tls;
+pub mod wildcard;
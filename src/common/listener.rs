@@ -0,0 +1,96 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::PathBuf;
+
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Listener {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl Listener {
+    // Accepts tcp://<addr>:<port>, unix://<path> or a bare <addr>:<port>.
+    pub fn parse(value: &str) -> Result<Listener, String> {
+        if let Some(path) = value.strip_prefix("unix://") {
+            if path.is_empty() {
+                return Err(format!("'{}' is missing a socket path", value));
+            }
+            return Ok(Listener::Unix(PathBuf::from(path)));
+        }
+        value
+            .strip_prefix("tcp://")
+            .unwrap_or(value)
+            .parse::<SocketAddr>()
+            .map(Listener::Tcp)
+            .map_err(|_| format!("'{}' is not a valid tcp:// or unix:// listener", value))
+    }
+
+    pub async fn bind(&self, socket_mode: u32) -> io::Result<BoundListener> {
+        match self {
+            Listener::Tcp(addr) => Ok(BoundListener::Tcp(TcpListener::bind(addr).await?)),
+            Listener::Unix(path) => {
+                // A socket left behind by a previous run would make the bind fail.
+                if let Ok(metadata) = fs::symlink_metadata(path) {
+                    if metadata.file_type().is_socket() {
+                        fs::remove_file(path)?;
+                    }
+                }
+                let listener = UnixListener::bind(path)?;
+                fs::set_permissions(path, fs::Permissions::from_mode(socket_mode))?;
+                Ok(BoundListener::Unix(listener, path.clone()))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Listener::Tcp(addr) => write!(f, "tcp://{}", addr),
+            Listener::Unix(path) => write!(f, "unix://{}", path.display()),
+        }
+    }
+}
+
+pub enum BoundListener {
+    Tcp(TcpListener),
+    Unix(UnixListener, PathBuf),
+}
+
+pub enum Connection {
+    Tcp(TcpStream, SocketAddr),
+    Unix(UnixStream),
+}
+
+impl BoundListener {
+    pub async fn accept(&self) -> io::Result<Connection> {
+        match self {
+            BoundListener::Tcp(listener) => {
+                let (stream, addr) = listener.accept().await?;
+                Ok(Connection::Tcp(stream, addr))
+            }
+            BoundListener::Unix(listener, _) => {
+                let (stream, _) = listener.accept().await?;
+                Ok(Connection::Unix(stream))
+            }
+        }
+    }
+}
+
+impl Drop for BoundListener {
+    fn drop(&mut self) {
+        if let BoundListener::Unix(_, path) = self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// Unix socket peers have no ip address, they are treated as local clients.
+pub fn unix_peer_addr() -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::LOCALHOST, 0))
+}
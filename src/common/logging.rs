@@ -0,0 +1,193 @@
+use std::collections::hash_map::RandomState;
+use std::fmt::Display;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{Map, Value};
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    pub fn names() -> &'static [&'static str] {
+        &["error", "warn", "info", "debug"]
+    }
+
+    pub fn from_name(name: &str) -> Option<LogLevel> {
+        match name {
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+    Logfmt,
+}
+
+impl LogFormat {
+    pub fn names() -> &'static [&'static str] {
+        &["text", "json", "logfmt"]
+    }
+
+    pub fn from_name(name: &str) -> Option<LogFormat> {
+        match name {
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            "logfmt" => Some(LogFormat::Logfmt),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Logger {
+    level: LogLevel,
+    format: LogFormat,
+}
+
+pub fn init(level: LogLevel, format: LogFormat) {
+    let _ = LOGGER.set(Logger { level, format });
+}
+
+fn logger() -> Logger {
+    *LOGGER.get_or_init(|| Logger {
+        level: LogLevel::Info,
+        format: LogFormat::Text,
+    })
+}
+
+pub fn enabled(level: LogLevel) -> bool {
+    level <= logger().level
+}
+
+pub fn error(message: &str, fields: &[(&str, &dyn Display)]) {
+    log(LogLevel::Error, message, fields)
+}
+
+pub fn warn(message: &str, fields: &[(&str, &dyn Display)]) {
+    log(LogLevel::Warn, message, fields)
+}
+
+pub fn info(message: &str, fields: &[(&str, &dyn Display)]) {
+    log(LogLevel::Info, message, fields)
+}
+
+pub fn debug(message: &str, fields: &[(&str, &dyn Display)]) {
+    log(LogLevel::Debug, message, fields)
+}
+
+pub fn log(level: LogLevel, message: &str, fields: &[(&str, &dyn Display)]) {
+    if !enabled(level) {
+        return;
+    }
+    let timestamp = format_timestamp(SystemTime::now());
+    let line = match logger().format {
+        LogFormat::Text => {
+            let mut line = format!(
+                "{} {:5} {}",
+                timestamp,
+                level.name().to_uppercase(),
+                message
+            );
+            for (key, value) in fields {
+                line.push_str(&format!(" {}={}", key, logfmt_value(&value.to_string())));
+            }
+            line
+        }
+        LogFormat::Logfmt => {
+            let mut line = format!(
+                "ts={} level={} msg={}",
+                timestamp,
+                level.name(),
+                logfmt_value(message)
+            );
+            for (key, value) in fields {
+                line.push_str(&format!(" {}={}", key, logfmt_value(&value.to_string())));
+            }
+            line
+        }
+        LogFormat::Json => {
+            let mut map = Map::new();
+            map.insert("ts".to_owned(), Value::String(timestamp));
+            map.insert("level".to_owned(), Value::String(level.name().to_owned()));
+            map.insert("msg".to_owned(), Value::String(message.to_owned()));
+            for (key, value) in fields {
+                map.insert(key.to_string(), Value::String(value.to_string()));
+            }
+            Value::Object(map).to_string()
+        }
+    };
+    let _ = writeln!(std::io::stderr(), "{}", line);
+}
+
+pub fn create_request_id() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    hasher.write_u128(nanos);
+    format!("{:016x}", hasher.finish())
+}
+
+fn logfmt_value(value: &str) -> String {
+    if !value.is_empty() && !value.contains(|c: char| c == ' ' || c == '"' || c == '=') {
+        value.to_owned()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
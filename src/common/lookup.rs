@@ -1,52 +1,235 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::net::IpAddr;
-use std::sync::Arc;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 
+use cidr::AnyIpCidr;
 use futures::{future, Future, FutureExt};
 use maxminddb::geoip2::City;
+use serde_json::Value;
 
 use crate::asn::AutonomousSystemNumber;
+use crate::data::{self, DataFilePaths, LookupData};
 use crate::dns::{DnsLookupResult, DnsLookupResultMx, DnsLookupResultSoa, DnsResolverHandle};
-use crate::ip::IpAsnDatabase;
-use crate::maxmind::MaxmindDatabase;
+use crate::geo::{self, DistanceResult};
+use crate::idn::{self, IdnLabelWarning};
+use crate::ip::IpAsnRecord;
+use crate::logging;
+use crate::maxmind::{MaxmindDatabaseType, MaxmindLookupResult};
+use crate::psl::SuffixLookupResult;
+use crate::status::{self, DataStatus};
+use crate::wildcard::{self, WildcardLookupResult, WildcardProbe};
 
 pub fn create_lookup_handler(
-    ip_asn_database: IpAsnDatabase,
-    maxmind_database: MaxmindDatabase,
+    data: LookupData,
+    data_paths: DataFilePaths,
     dns_resolver_handle: DnsResolverHandle,
 ) -> LookupHandler {
     LookupHandler {
-        ip_asn_database: Arc::new(ip_asn_database),
-        maxmind_database: Arc::new(maxmind_database),
+        data: Arc::new(RwLock::new(Arc::new(data))),
+        data_paths: Arc::new(data_paths),
+        reloading: Arc::new(AtomicBool::new(false)),
         dns_resolver_handle,
     }
 }
 
 #[derive(Clone)]
 pub struct LookupHandler {
-    ip_asn_database: Arc<IpAsnDatabase>,
-    maxmind_database: Arc<MaxmindDatabase>,
+    data: Arc<RwLock<Arc<LookupData>>>,
+    data_paths: Arc<DataFilePaths>,
+    reloading: Arc<AtomicBool>,
     dns_resolver_handle: DnsResolverHandle,
 }
 
 impl LookupHandler {
+    pub fn with_request_id(&self, request_id: &str) -> LookupHandler {
+        LookupHandler {
+            dns_resolver_handle: self.dns_resolver_handle.with_request_id(request_id),
+            ..self.clone()
+        }
+    }
+
+    fn data(&self) -> Arc<LookupData> {
+        self.data.read().unwrap().clone()
+    }
+
+    pub fn data_status(&self, max_age: Duration) -> DataStatus {
+        status::create_data_status(&self.data().sources, max_age)
+    }
+
+    // Most recent modification time of the data files currently being served.
+    pub fn data_last_modified(&self) -> Option<SystemTime> {
+        self.data().modified_times.values().flatten().max().cloned()
+    }
+
+    pub fn min_dns_ttl(&self) -> Option<u32> {
+        self.dns_resolver_handle.min_ttl()
+    }
+
+    pub fn dns_failed(&self) -> bool {
+        self.dns_resolver_handle.had_failures()
+    }
+
+    pub fn data_files_modified(&self) -> BTreeMap<PathBuf, Option<SystemTime>> {
+        self.data_paths.modified_times()
+    }
+
+    pub fn data_files_changed(
+        &self,
+        modified_times: &BTreeMap<PathBuf, Option<SystemTime>>,
+    ) -> bool {
+        self.data().modified_times != *modified_times
+    }
+
+    // Loads a complete new set of data files and only swaps it in once it has loaded and
+    // validated, lookups in flight keep using the set they started with.
+    pub fn reload(&self) -> Result<(), String> {
+        if self.reloading.swap(true, Ordering::SeqCst) {
+            return Err("a reload is already in progress".to_owned());
+        }
+        let started = Instant::now();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            data::load_lookup_data(&self.data_paths)
+        }))
+        .map_err(|_| "loading data files failed".to_owned())
+        .and_then(|replacement| {
+            self.data().validate_replacement(&replacement)?;
+            *self.data.write().unwrap() = Arc::new(replacement);
+            Ok(())
+        });
+        self.reloading.store(false, Ordering::SeqCst);
+
+        match &result {
+            Ok(()) => logging::info(
+                "reloaded data files",
+                &[(
+                    "duration_ms",
+                    &format!("{:.3}", started.elapsed().as_secs_f64() * 1000.0),
+                )],
+            ),
+            Err(err) => logging::warn(
+                "data file reload failed, keeping current data",
+                &[("error", err)],
+            ),
+        }
+        result
+    }
+
+    pub async fn lookup(&self, query: String, options: &DomainLookupOptions) -> LookupResult {
+        match query.parse::<IpAddr>() {
+            Ok(ip) => LookupResult::Ip(self.lookup_ip(ip).await),
+            Err(_) => LookupResult::Domain(self.lookup_domain_with_options(query, options).await),
+        }
+    }
+
     pub async fn lookup_ip(&self, ip: IpAddr) -> IpLookupResult {
         let dns_names = self.dns_resolver_handle.reverse_dns_lookup(ip).await;
-        let asn_lookup_result = self.ip_asn_database.lookup(ip).map(|r| r.clone());
-        let city_lookup_result = self.maxmind_database.lookup_city(ip);
+        let data = self.data();
+        let asn_lookup_result = data
+            .ip_asn_database
+            .as_ref()
+            .and_then(|db| db.lookup(ip))
+            .map(|r| r.clone());
+        let city_lookup_result = data.maxmind_database.lookup_city(ip);
+        let ptr_names = dns_names
+            .iter()
+            .map(|name| PtrLookupResult {
+                name: name.clone(),
+                suffix: self.lookup_suffix(name),
+            })
+            .collect();
 
+        // Fields backed by a disabled data source are left out, a source that is loaded but
+        // has no match answers with null.
+        let ip_asn_loaded = data.ip_asn_database.is_some();
+        let asn_loaded = ip_asn_loaded && data.asn_database.is_some();
+        let city_loaded = data
+            .maxmind_database
+            .has_database(MaxmindDatabaseType::City);
         IpLookupResult {
             ip: ip.clone(),
-            asn: asn_lookup_result.and_then(|r| r.asn.clone()),
-            geo: city_lookup_result,
+            prefix: Some(asn_lookup_result.as_ref().map(|r| r.prefix.clone()))
+                .filter(|_| ip_asn_loaded),
+            asn: Some(asn_lookup_result.and_then(|r| r.asn.clone())).filter(|_| asn_loaded),
+            geo: Some(city_lookup_result).filter(|_| city_loaded),
+            maxmind: data.maxmind_database.lookup_all(ip),
+            extra: data.maxmind_database.lookup_custom(ip),
             reverse_dns: Some(dns_names),
+            ptr: ptr_names,
+        }
+    }
+
+    pub fn lookup_asn(&self, asn_id: u32) -> AsnLookupResult {
+        let data = self.data();
+        let prefixes = data
+            .ip_asn_database
+            .as_ref()
+            .map(|db| db.lookup_asn_prefixes(asn_id))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|r| r.prefix.clone())
+            .collect();
+        AsnLookupResult {
+            id: asn_id,
+            asn: data.asn_database.as_ref().and_then(|db| db.lookup(asn_id)),
+            prefixes,
         }
     }
 
+    pub fn lookup_prefix(&self, prefix: AnyIpCidr) -> PrefixLookupResult {
+        let (start, end) = match (prefix.first_address(), prefix.last_address()) {
+            (Some(start), Some(end)) => (start, end),
+            _ => {
+                return PrefixLookupResult {
+                    prefix,
+                    covering: None,
+                    more_specifics: Vec::new(),
+                }
+            }
+        };
+        let data = self.data();
+        let db = data.ip_asn_database.as_ref();
+        let covering = db
+            .and_then(|db| db.lookup(start))
+            .filter(|r| r.start <= start && r.end >= end)
+            .map(RoutedPrefix::from_record);
+        let more_specifics = db
+            .map(|db| db.lookup_range(start, end))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|r| r.prefix != prefix)
+            .map(RoutedPrefix::from_record)
+            .collect();
+        PrefixLookupResult {
+            prefix,
+            covering,
+            more_specifics,
+        }
+    }
+
+    pub async fn lookup_distance(&self, from: IpAddr, to: IpAddr) -> DistanceResult {
+        let (from, to) = future::join(self.lookup_ip(from), self.lookup_ip(to)).await;
+        geo::create_distance_result(from, to)
+    }
+
     pub async fn lookup_ip_sync(&self, ip: IpAddr) -> IpLookupResult {
         self.lookup_ip(ip).await
     }
 
     pub async fn lookup_domain(&self, domain: String) -> DomainLookupResult {
+        self.lookup_domain_with_options(domain, &DomainLookupOptions::default())
+            .await
+    }
+
+    pub async fn lookup_domain_with_options(
+        &self,
+        domain: String,
+        options: &DomainLookupOptions,
+    ) -> DomainLookupResult {
+        let idn_domain = idn::analyze_domain(&domain);
         let dns = self.dns_resolver_handle.dns_lookup(domain.clone()).await;
         let handler = &self.clone();
 
@@ -56,8 +239,27 @@ impl LookupHandler {
 
         let ips = future::join_all(ipaddrs.into_iter().map(move |ip| handler.lookup_ip(ip))).await;
 
+        let mx_hosts = future::join_all(
+            dns.mx
+                .iter()
+                .map(|mx| handler.lookup_host(mx.exchange.clone())),
+        )
+        .await;
+        let ns_hosts =
+            future::join_all(dns.ns.iter().map(|ns| handler.lookup_host(ns.clone()))).await;
+
+        let wildcard = if options.detect_wildcard {
+            self.detect_wildcard(&domain, &dns).await
+        } else {
+            None
+        };
+
         DomainLookupResult {
             domain: domain.clone(),
+            domain_ascii: idn_domain.ascii,
+            domain_unicode: idn_domain.unicode,
+            idn_warnings: idn_domain.warnings,
+            suffix: self.lookup_suffix(&domain),
             ipv4: ips
                 .iter()
                 .map(|r| r.to_owned())
@@ -73,7 +275,58 @@ impl LookupHandler {
             mx: dns.mx,
             txt: dns.txt,
             soa: dns.soa,
+            mx_summary: HostSummary::from_hosts(&mx_hosts),
+            ns_summary: HostSummary::from_hosts(&ns_hosts),
+            mx_hosts,
+            ns_hosts,
+            wildcard,
+        }
+    }
+
+    async fn detect_wildcard(
+        &self,
+        domain: &str,
+        dns: &DnsLookupResult,
+    ) -> Option<WildcardLookupResult> {
+        let domain = idn::to_ascii(domain)?;
+        let zone = wildcard::parent_zone(&domain, self.lookup_suffix(&domain).as_ref())?;
+        let probe = wildcard::probe_zone(&self.dns_resolver_handle, &zone).await;
+        Some(probe.to_result(&dns.addresses()))
+    }
+
+    fn lookup_suffix(&self, name: &str) -> Option<SuffixLookupResult> {
+        self.data()
+            .public_suffix_list
+            .as_ref()
+            .and_then(|psl| psl.lookup(name))
+    }
+
+    async fn lookup_host(&self, host: String) -> HostLookupResult {
+        let ipaddrs = self.dns_resolver_handle.host_lookup(host.clone()).await;
+        let ips = future::join_all(ipaddrs.into_iter().map(|ip| self.lookup_ip(ip))).await;
+        HostLookupResult { host, ips }
+    }
+
+    pub async fn probe_wildcard(&self, zone: &str) -> WildcardProbe {
+        wildcard::probe_zone(&self.dns_resolver_handle, zone).await
+    }
+
+    pub async fn lookup_subdomain(
+        &self,
+        name: String,
+        probe: &WildcardProbe,
+    ) -> Option<HostLookupResult> {
+        let answer = self.dns_resolver_handle.address_lookup(name.clone()).await;
+        if answer.is_empty() || probe.matches(&answer) {
+            return None;
         }
+
+        let mut ipaddrs: Vec<IpAddr> = Vec::new();
+        ipaddrs.append(&mut answer.a.into_iter().map(IpAddr::from).collect());
+        ipaddrs.append(&mut answer.aaaa.into_iter().map(IpAddr::from).collect());
+        let ips = future::join_all(ipaddrs.into_iter().map(|ip| self.lookup_ip(ip))).await;
+
+        Some(HostLookupResult { host: name, ips })
     }
 
     pub async fn lookup_domain_sync(&self, domain: String) -> DomainLookupResult {
@@ -81,17 +334,166 @@ impl LookupHandler {
     }
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct DomainLookupOptions {
+    pub detect_wildcard: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum LookupResult {
+    Ip(IpLookupResult),
+    Domain(DomainLookupResult),
+}
+
 #[derive(Serialize, Debug, Clone)]
 pub struct IpLookupResult {
     ip: IpAddr,
-    asn: Option<Arc<AutonomousSystemNumber>>,
-    geo: Option<City>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prefix: Option<Option<AnyIpCidr>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asn: Option<Option<Arc<AutonomousSystemNumber>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    geo: Option<Option<City>>,
+    #[serde(skip_serializing_if = "MaxmindLookupResult::is_empty")]
+    maxmind: MaxmindLookupResult,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    extra: BTreeMap<String, Value>,
     reverse_dns: Option<Vec<String>>,
+    ptr: Vec<PtrLookupResult>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PtrLookupResult {
+    pub name: String,
+    #[serde(flatten)]
+    pub suffix: Option<SuffixLookupResult>,
+}
+
+impl IpLookupResult {
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    pub fn prefix(&self) -> Option<&AnyIpCidr> {
+        self.prefix.as_ref().and_then(|prefix| prefix.as_ref())
+    }
+
+    pub fn asn(&self) -> Option<&AutonomousSystemNumber> {
+        self.asn
+            .as_ref()
+            .and_then(|asn| asn.as_ref())
+            .map(|asn| asn.as_ref())
+    }
+
+    pub fn geo(&self) -> Option<&City> {
+        self.geo.as_ref().and_then(|geo| geo.as_ref())
+    }
+
+    pub fn reverse_dns(&self) -> &[String] {
+        self.reverse_dns
+            .as_ref()
+            .map(|n| n.as_slice())
+            .unwrap_or(&[])
+    }
+
+    fn asn_id(&self) -> Option<u32> {
+        self.asn().map(|asn| asn.id())
+    }
+
+    fn country_code(&self) -> Option<String> {
+        self.geo()
+            .and_then(|geo| geo.country.as_ref())
+            .and_then(|country| country.iso_code.clone())
+    }
+
+    // Where the asn is registered, which often differs from where the address is used.
+    fn asn_country(&self) -> Option<String> {
+        self.asn().map(|asn| asn.country().to_owned())
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AsnLookupResult {
+    pub id: u32,
+    pub asn: Option<Arc<AutonomousSystemNumber>>,
+    pub prefixes: Vec<AnyIpCidr>,
+}
+
+impl AsnLookupResult {
+    pub fn is_empty(&self) -> bool {
+        self.asn.is_none() && self.prefixes.is_empty()
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RoutedPrefix {
+    pub prefix: AnyIpCidr,
+    pub asn_id: u32,
+    pub asn: Option<Arc<AutonomousSystemNumber>>,
+}
+
+impl RoutedPrefix {
+    fn from_record(record: &IpAsnRecord) -> RoutedPrefix {
+        RoutedPrefix {
+            prefix: record.prefix.clone(),
+            asn_id: record.asn_id,
+            asn: record.asn.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PrefixLookupResult {
+    pub prefix: AnyIpCidr,
+    pub covering: Option<RoutedPrefix>,
+    pub more_specifics: Vec<RoutedPrefix>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct HostLookupResult {
+    pub host: String,
+    pub ips: Vec<IpLookupResult>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct HostSummary {
+    pub hosts: usize,
+    pub resolved_hosts: usize,
+    pub asn_count: usize,
+    pub asns: Vec<u32>,
+    pub country_count: usize,
+    pub countries: Vec<String>,
+    pub asn_countries: Vec<String>,
+}
+
+impl HostSummary {
+    fn from_hosts(hosts: &[HostLookupResult]) -> HostSummary {
+        let ips = hosts.iter().flat_map(|h| h.ips.iter());
+        let asns: BTreeSet<u32> = ips.clone().filter_map(|ip| ip.asn_id()).collect();
+        let countries: BTreeSet<String> = ips.clone().filter_map(|ip| ip.country_code()).collect();
+        let asn_countries: BTreeSet<String> = ips.filter_map(|ip| ip.asn_country()).collect();
+
+        HostSummary {
+            hosts: hosts.len(),
+            resolved_hosts: hosts.iter().filter(|h| !h.ips.is_empty()).count(),
+            asn_count: asns.len(),
+            asns: asns.into_iter().collect(),
+            country_count: countries.len(),
+            countries: countries.into_iter().collect(),
+            asn_countries: asn_countries.into_iter().collect(),
+        }
+    }
 }
 
 #[derive(Serialize, Debug, Clone)]
 pub struct DomainLookupResult {
     pub domain: String,
+    pub domain_ascii: String,
+    pub domain_unicode: String,
+    pub idn_warnings: Vec<IdnLabelWarning>,
+    #[serde(flatten)]
+    pub suffix: Option<SuffixLookupResult>,
     pub ipv4: Vec<IpLookupResult>,
     pub ipv6: Vec<IpLookupResult>,
     pub cname: Vec<String>,
@@ -99,4 +501,9 @@ pub struct DomainLookupResult {
     pub mx: Vec<DnsLookupResultMx>,
     pub txt: Vec<String>,
     pub soa: Option<DnsLookupResultSoa>,
+    pub mx_hosts: Vec<HostLookupResult>,
+    pub ns_hosts: Vec<HostLookupResult>,
+    pub mx_summary: HostSummary,
+    pub ns_summary: HostSummary,
+    pub wildcard: Option<WildcardLookupResult>,
 }
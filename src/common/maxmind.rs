@@ -1,20 +1,357 @@
+use std::collections::BTreeMap;
 use std::net::IpAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use maxminddb::geoip2::City;
+use maxminddb::geoip2::{City, Country};
 use maxminddb::Reader;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
 
-pub fn load_maxmind_database(mm_file: &Path) -> MaxmindDatabase {
-    let reader = Reader::open(&mm_file.to_string_lossy()).unwrap();
-    MaxmindDatabase { reader: reader }
+use crate::logging;
+use crate::status::{self, DataFileInfo, DisabledDataSource};
+
+pub fn load_maxmind_database(
+    mm_files: &[PathBuf],
+    custom_mm_files: &[(String, PathBuf)],
+) -> MaxmindDatabase {
+    let mut readers = BTreeMap::new();
+    let mut sources: BTreeMap<MaxmindDatabaseType, &PathBuf> = BTreeMap::new();
+    let mut infos = Vec::new();
+    let mut disabled = Vec::new();
+    for mm_file in mm_files {
+        let reader = match Reader::open(&mm_file.to_string_lossy()) {
+            Ok(reader) => reader,
+            Err(err) => {
+                disabled.push(status::create_disabled_data_source(
+                    "maxmind",
+                    mm_file,
+                    err.to_string(),
+                ));
+                continue;
+            }
+        };
+        match MaxmindDatabaseType::from_database_type(&reader.metadata.database_type) {
+            Some(database_type) => {
+                // Later files win so an explicit --maxmind-database replaces a default one.
+                if let Some(replaced) = sources.insert(database_type, mm_file) {
+                    logging::warn(
+                        "replacing maxmind database with the same database type",
+                        &[
+                            ("file", &mm_file.display()),
+                            ("replaced", &replaced.display()),
+                            ("database_type", &reader.metadata.database_type),
+                        ],
+                    );
+                    infos.retain(|info: &DataFileInfo| &info.path != replaced);
+                }
+                infos.push(create_maxmind_file_info("maxmind", mm_file, &reader));
+                readers.insert(database_type, reader);
+            }
+            None => logging::warn(
+                "skipping maxmind database with unsupported database type",
+                &[
+                    ("file", &mm_file.display()),
+                    ("database_type", &reader.metadata.database_type),
+                ],
+            ),
+        }
+    }
+    let mut custom_readers = BTreeMap::new();
+    for (name, mm_file) in custom_mm_files {
+        let source_name = format!("mmdb-{}", name);
+        match Reader::open(&mm_file.to_string_lossy()) {
+            Ok(reader) => {
+                infos.push(create_maxmind_file_info(&source_name, mm_file, &reader));
+                custom_readers.insert(name.clone(), reader);
+            }
+            Err(err) => disabled.push(status::create_disabled_data_source(
+                &source_name,
+                mm_file,
+                err.to_string(),
+            )),
+        }
+    }
+    MaxmindDatabase {
+        readers,
+        custom_readers,
+        infos,
+        disabled,
+    }
+}
+
+fn create_maxmind_file_info(name: &str, mm_file: &Path, reader: &Reader) -> DataFileInfo {
+    let mut info = status::create_data_file_info(name, mm_file);
+    info.node_count = Some(reader.metadata.node_count);
+    info.database_type = Some(reader.metadata.database_type.clone());
+    info.build_epoch = Some(reader.metadata.build_epoch);
+    info
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MaxmindDatabaseType {
+    City,
+    Country,
+    Asn,
+    Isp,
+    ConnectionType,
+    Domain,
+    AnonymousIp,
+}
+
+impl MaxmindDatabaseType {
+    pub fn from_database_type(database_type: &str) -> Option<MaxmindDatabaseType> {
+        let edition = database_type
+            .trim_start_matches("GeoLite2-")
+            .trim_start_matches("GeoIP2-");
+        match edition {
+            "City" | "Enterprise" => Some(MaxmindDatabaseType::City),
+            "Country" => Some(MaxmindDatabaseType::Country),
+            "ASN" => Some(MaxmindDatabaseType::Asn),
+            "ISP" => Some(MaxmindDatabaseType::Isp),
+            "Connection-Type" => Some(MaxmindDatabaseType::ConnectionType),
+            "Domain" => Some(MaxmindDatabaseType::Domain),
+            "Anonymous-IP" => Some(MaxmindDatabaseType::AnonymousIp),
+            _ => None,
+        }
+    }
 }
 
 pub struct MaxmindDatabase {
-    reader: Reader,
+    readers: BTreeMap<MaxmindDatabaseType, Reader>,
+    custom_readers: BTreeMap<String, Reader>,
+    infos: Vec<DataFileInfo>,
+    disabled: Vec<DisabledDataSource>,
 }
 
 impl MaxmindDatabase {
+    pub fn infos(&self) -> &[DataFileInfo] {
+        &self.infos
+    }
+
+    pub fn disabled(&self) -> &[DisabledDataSource] {
+        &self.disabled
+    }
+
     pub fn lookup_city(&self, ip: IpAddr) -> Option<City> {
-        self.reader.lookup::<City>(ip).ok()
+        self.lookup(MaxmindDatabaseType::City, ip)
+    }
+
+    pub fn lookup_country(&self, ip: IpAddr) -> Option<Country> {
+        self.lookup(MaxmindDatabaseType::Country, ip)
+    }
+
+    pub fn lookup_asn(&self, ip: IpAddr) -> Option<MaxmindAsn> {
+        self.lookup(MaxmindDatabaseType::Asn, ip)
+    }
+
+    pub fn lookup_isp(&self, ip: IpAddr) -> Option<MaxmindIsp> {
+        self.lookup(MaxmindDatabaseType::Isp, ip)
+    }
+
+    pub fn lookup_connection_type(&self, ip: IpAddr) -> Option<MaxmindConnectionType> {
+        self.lookup(MaxmindDatabaseType::ConnectionType, ip)
+    }
+
+    pub fn lookup_domain(&self, ip: IpAddr) -> Option<MaxmindDomain> {
+        self.lookup(MaxmindDatabaseType::Domain, ip)
+    }
+
+    pub fn lookup_anonymous_ip(&self, ip: IpAddr) -> Option<MaxmindAnonymousIp> {
+        self.lookup(MaxmindDatabaseType::AnonymousIp, ip)
+    }
+
+    pub fn lookup_all(&self, ip: IpAddr) -> MaxmindLookupResult {
+        MaxmindLookupResult {
+            country: self.lookup_country(ip),
+            asn: self.lookup_asn(ip),
+            isp: self.lookup_isp(ip),
+            connection_type: self.lookup_connection_type(ip),
+            domain: self.lookup_domain(ip),
+            anonymous_ip: self.lookup_anonymous_ip(ip),
+        }
+    }
+
+    pub fn lookup_custom(&self, ip: IpAddr) -> BTreeMap<String, Value> {
+        self.custom_readers
+            .iter()
+            .filter_map(|(name, reader)| {
+                reader
+                    .lookup::<Value>(ip)
+                    .ok()
+                    .filter(|v| !v.is_null())
+                    .map(|v| (name.clone(), v))
+            })
+            .collect()
+    }
+
+    pub fn has_database(&self, database_type: MaxmindDatabaseType) -> bool {
+        self.readers.contains_key(&database_type)
+    }
+
+    fn lookup<T: DeserializeOwned>(
+        &self,
+        database_type: MaxmindDatabaseType,
+        ip: IpAddr,
+    ) -> Option<T> {
+        self.readers
+            .get(&database_type)
+            .and_then(|reader| reader.lookup::<T>(ip).ok())
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MaxmindLookupResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<Country>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn: Option<MaxmindAsn>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub isp: Option<MaxmindIsp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection_type: Option<MaxmindConnectionType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<MaxmindDomain>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anonymous_ip: Option<MaxmindAnonymousIp>,
+}
+
+impl MaxmindLookupResult {
+    pub fn is_empty(&self) -> bool {
+        self.country.is_none()
+            && self.asn.is_none()
+            && self.isp.is_none()
+            && self.connection_type.is_none()
+            && self.domain.is_none()
+            && self.anonymous_ip.is_none()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MaxmindAsn {
+    pub autonomous_system_number: Option<u32>,
+    pub autonomous_system_organization: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MaxmindIsp {
+    pub autonomous_system_number: Option<u32>,
+    pub autonomous_system_organization: Option<String>,
+    pub isp: Option<String>,
+    pub organization: Option<String>,
+    pub mobile_country_code: Option<String>,
+    pub mobile_network_code: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MaxmindConnectionType {
+    pub connection_type: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MaxmindDomain {
+    pub domain: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MaxmindAnonymousIp {
+    pub is_anonymous: Option<bool>,
+    pub is_anonymous_vpn: Option<bool>,
+    pub is_hosting_provider: Option<bool>,
+    pub is_public_proxy: Option<bool>,
+    pub is_residential_proxy: Option<bool>,
+    pub is_tor_exit_node: Option<bool>,
+}
+
+pub fn localized_name(
+    names: Option<&BTreeMap<String, String>>,
+    languages: &[String],
+) -> Option<String> {
+    let names = names?;
+    languages
+        .iter()
+        .map(|l| l.as_str())
+        .chain(std::iter::once("en"))
+        .find_map(|lang| {
+            names
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(lang))
+                .or_else(|| {
+                    names.iter().find(|(key, _)| {
+                        primary_subtag(key).eq_ignore_ascii_case(primary_subtag(lang))
+                    })
+                })
+                .map(|(_, name)| name.clone())
+        })
+}
+
+fn primary_subtag(lang: &str) -> &str {
+    lang.split(|c| c == '-' || c == '_').next().unwrap_or(lang)
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LocalizedCity {
+    pub continent: Option<String>,
+    pub continent_code: Option<String>,
+    pub country: Option<String>,
+    pub country_code: Option<String>,
+    pub registered_country: Option<String>,
+    pub registered_country_code: Option<String>,
+    pub subdivisions: Vec<LocalizedSubdivision>,
+    pub city: Option<String>,
+    pub postal_code: Option<String>,
+    pub time_zone: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub accuracy_radius: Option<u16>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LocalizedSubdivision {
+    pub name: Option<String>,
+    pub code: Option<String>,
+}
+
+impl LocalizedCity {
+    pub fn new(city: &City, languages: &[String]) -> LocalizedCity {
+        let location = city.location.as_ref();
+        LocalizedCity {
+            continent: city
+                .continent
+                .as_ref()
+                .and_then(|c| localized_name(c.names.as_ref(), languages)),
+            continent_code: city.continent.as_ref().and_then(|c| c.code.clone()),
+            country: city
+                .country
+                .as_ref()
+                .and_then(|c| localized_name(c.names.as_ref(), languages)),
+            country_code: city.country.as_ref().and_then(|c| c.iso_code.clone()),
+            registered_country: city
+                .registered_country
+                .as_ref()
+                .and_then(|c| localized_name(c.names.as_ref(), languages)),
+            registered_country_code: city
+                .registered_country
+                .as_ref()
+                .and_then(|c| c.iso_code.clone()),
+            subdivisions: city
+                .subdivisions
+                .iter()
+                .flatten()
+                .map(|s| LocalizedSubdivision {
+                    name: localized_name(s.names.as_ref(), languages),
+                    code: s.iso_code.clone(),
+                })
+                .collect(),
+            city: city
+                .city
+                .as_ref()
+                .and_then(|c| localized_name(c.names.as_ref(), languages)),
+            postal_code: city.postal.as_ref().and_then(|p| p.code.clone()),
+            time_zone: location.and_then(|l| l.time_zone.clone()),
+            latitude: location.and_then(|l| l.latitude),
+            longitude: location.and_then(|l| l.longitude),
+            accuracy_radius: location.and_then(|l| l.accuracy_radius),
+        }
     }
 }
@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::status::DataStatus;
+
+static LATENCY_BUCKETS: &'static [f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; LATENCY_BUCKETS.len()];
+        }
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS.iter()) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        for (bucket, bound) in self.buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+            writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, bucket
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, self.count
+        )
+        .unwrap();
+        writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum).unwrap();
+        writeln!(out, "{}_count{{{}}} {}", name, labels, self.count).unwrap();
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    http_requests: Mutex<BTreeMap<(String, u16), Histogram>>,
+    dns_queries: Mutex<BTreeMap<(String, String), Histogram>>,
+    dns_errors: Mutex<BTreeMap<(String, String), u64>>,
+    api_key_requests: Mutex<BTreeMap<(String, u16), u64>>,
+    rate_limited: Mutex<BTreeMap<String, u64>>,
+    cache_requests: Mutex<BTreeMap<(String, bool), u64>>,
+    in_flight: AtomicI64,
+}
+
+// Leaves the in-flight gauge when dropped, which also covers requests whose future is
+// dropped because the client went away.
+pub struct InFlight<'a> {
+    metrics: &'a Metrics,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.metrics.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    pub fn start_request(&self) -> InFlight<'_> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight { metrics: self }
+    }
+
+    pub fn finish_request(&self, route: &str, status: u16, elapsed: Duration) {
+        self.http_requests
+            .lock()
+            .unwrap()
+            .entry((route.to_owned(), status))
+            .or_default()
+            .observe(elapsed);
+    }
+
+    pub fn record_dns_query(&self, rtype: &str, upstream: &str, elapsed: Duration, ok: bool) {
+        let key = (rtype.to_owned(), upstream.to_owned());
+        if !ok {
+            *self
+                .dns_errors
+                .lock()
+                .unwrap()
+                .entry(key.clone())
+                .or_default() += 1;
+        }
+        self.dns_queries
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_default()
+            .observe(elapsed);
+    }
+
+    pub fn record_api_key_request(&self, key: &str, status: u16) {
+        *self
+            .api_key_requests
+            .lock()
+            .unwrap()
+            .entry((key.to_owned(), status))
+            .or_default() += 1;
+    }
+
+    pub fn record_rate_limited(&self, limit: &str) {
+        *self
+            .rate_limited
+            .lock()
+            .unwrap()
+            .entry(limit.to_owned())
+            .or_default() += 1;
+    }
+
+    // The daemon keeps no response cache of its own, cache hits are conditional requests
+    // whose ETag still matched and were answered with 304.
+    pub fn record_cache_request(&self, route: &str, hit: bool) {
+        *self
+            .cache_requests
+            .lock()
+            .unwrap()
+            .entry((route.to_owned(), hit))
+            .or_default() += 1;
+    }
+
+    pub fn render(&self, data_status: &DataStatus) -> String {
+        let mut out = String::new();
+
+        out.push_str(
+            "# HELP net_lookup_http_requests_in_flight Requests currently being served.\n",
+        );
+        out.push_str("# TYPE net_lookup_http_requests_in_flight gauge\n");
+        writeln!(
+            out,
+            "net_lookup_http_requests_in_flight {}",
+            self.in_flight.load(Ordering::Relaxed)
+        )
+        .unwrap();
+
+        out.push_str("# HELP net_lookup_http_request_duration_seconds Request latency by route and status.\n");
+        out.push_str("# TYPE net_lookup_http_request_duration_seconds histogram\n");
+        for ((route, status), histogram) in self.http_requests.lock().unwrap().iter() {
+            let labels = format!("route=\"{}\",status=\"{}\"", escape(route), status);
+            histogram.render(
+                &mut out,
+                "net_lookup_http_request_duration_seconds",
+                &labels,
+            );
+        }
+
+        out.push_str("# HELP net_lookup_dns_query_duration_seconds DNS query latency by record type and upstream.\n");
+        out.push_str("# TYPE net_lookup_dns_query_duration_seconds histogram\n");
+        for ((rtype, upstream), histogram) in self.dns_queries.lock().unwrap().iter() {
+            let labels = format!("rtype=\"{}\",upstream=\"{}\"", rtype, escape(upstream));
+            histogram.render(&mut out, "net_lookup_dns_query_duration_seconds", &labels);
+        }
+
+        out.push_str("# HELP net_lookup_dns_query_errors_total Failed DNS queries by record type and upstream.\n");
+        out.push_str("# TYPE net_lookup_dns_query_errors_total counter\n");
+        for ((rtype, upstream), errors) in self.dns_errors.lock().unwrap().iter() {
+            writeln!(
+                out,
+                "net_lookup_dns_query_errors_total{{rtype=\"{}\",upstream=\"{}\"}} {}",
+                rtype,
+                escape(upstream),
+                errors
+            )
+            .unwrap();
+        }
+
+        out.push_str(
+            "# HELP net_lookup_api_key_requests_total Requests by api key name and status.\n",
+        );
+        out.push_str("# TYPE net_lookup_api_key_requests_total counter\n");
+        for ((key, status), requests) in self.api_key_requests.lock().unwrap().iter() {
+            writeln!(
+                out,
+                "net_lookup_api_key_requests_total{{key=\"{}\",status=\"{}\"}} {}",
+                escape(key),
+                status,
+                requests
+            )
+            .unwrap();
+        }
+
+        out.push_str(
+            "# HELP net_lookup_rate_limited_total Requests rejected by the key or ip rate limit.\n",
+        );
+        out.push_str("# TYPE net_lookup_rate_limited_total counter\n");
+        for (limit, rejected) in self.rate_limited.lock().unwrap().iter() {
+            writeln!(
+                out,
+                "net_lookup_rate_limited_total{{limit=\"{}\"}} {}",
+                limit, rejected
+            )
+            .unwrap();
+        }
+
+        out.push_str("# HELP net_lookup_http_cache_requests_total Conditional requests by route, hits were answered with 304.\n");
+        out.push_str("# TYPE net_lookup_http_cache_requests_total counter\n");
+        for ((route, hit), requests) in self.cache_requests.lock().unwrap().iter() {
+            writeln!(
+                out,
+                "net_lookup_http_cache_requests_total{{route=\"{}\",result=\"{}\"}} {}",
+                escape(route),
+                if *hit { "hit" } else { "miss" },
+                requests
+            )
+            .unwrap();
+        }
+
+        out.push_str("# HELP net_lookup_data_file_age_seconds Age of each loaded data file.\n");
+        out.push_str("# TYPE net_lookup_data_file_age_seconds gauge\n");
+        for file in data_status.files.iter() {
+            if let Some(age) = file.age_seconds {
+                writeln!(
+                    out,
+                    "net_lookup_data_file_age_seconds{{name=\"{}\",path=\"{}\"}} {}",
+                    escape(&file.info.name),
+                    escape(&file.info.path.to_string_lossy()),
+                    age
+                )
+                .unwrap();
+            }
+        }
+
+        out.push_str(
+            "# HELP net_lookup_data_file_records Number of records in each loaded data file.\n",
+        );
+        out.push_str("# TYPE net_lookup_data_file_records gauge\n");
+        for file in data_status.files.iter() {
+            if let Some(records) = file
+                .info
+                .records
+                .or(file.info.node_count.map(|n| n as usize))
+            {
+                writeln!(
+                    out,
+                    "net_lookup_data_file_records{{name=\"{}\",path=\"{}\"}} {}",
+                    escape(&file.info.name),
+                    escape(&file.info.path.to_string_lossy()),
+                    records
+                )
+                .unwrap();
+            }
+        }
+
+        out.push_str("# HELP net_lookup_data_source_disabled Data sources that failed to load.\n");
+        out.push_str("# TYPE net_lookup_data_source_disabled gauge\n");
+        for disabled in data_status.disabled.iter() {
+            writeln!(
+                out,
+                "net_lookup_data_source_disabled{{name=\"{}\",path=\"{}\"}} 1",
+                escape(&disabled.name),
+                escape(&disabled.path.to_string_lossy())
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
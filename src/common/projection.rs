@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+
+use maxminddb::geoip2::City;
+use serde_json::{Map, Value};
+
+use crate::maxmind::LocalizedCity;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoView {
+    Compact,
+    Full,
+    Localized(Vec<String>),
+}
+
+impl GeoView {
+    pub fn localized(languages: &str) -> GeoView {
+        GeoView::Localized(parse_accept_language(languages))
+    }
+}
+
+pub fn parse_accept_language(header: &str) -> Vec<String> {
+    let mut languages: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if tag.is_empty() || tag == "*" || quality <= 0.0 {
+                None
+            } else {
+                Some((tag.to_owned(), quality))
+            }
+        })
+        .collect();
+    languages.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    languages.into_iter().map(|(tag, _)| tag).collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct Projection {
+    fields: Option<FieldTree>,
+    columns: Vec<String>,
+    geo_view: GeoView,
+}
+
+#[derive(Debug, Clone, Default)]
+struct FieldTree {
+    whole: bool,
+    children: BTreeMap<String, FieldTree>,
+}
+
+impl Projection {
+    pub fn new(fields: Option<&str>, geo_view: GeoView) -> Projection {
+        let paths: Vec<String> = fields
+            .map(|f| {
+                f.split(',')
+                    .map(|path| path.trim().to_owned())
+                    .filter(|path| !path.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let fields = if paths.is_empty() {
+            None
+        } else {
+            let mut tree = FieldTree::default();
+            for path in paths.iter() {
+                tree.insert(&path.split('.').collect::<Vec<_>>());
+            }
+            Some(tree)
+        };
+
+        Projection {
+            fields,
+            columns: paths,
+            geo_view,
+        }
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn languages(&self) -> &[String] {
+        match &self.geo_view {
+            GeoView::Localized(languages) => languages,
+            _ => &[],
+        }
+    }
+
+    pub fn apply(&self, value: Value) -> Value {
+        let value = match &self.geo_view {
+            GeoView::Compact => map_geo_fields(value, &compact_geo),
+            GeoView::Full => value,
+            GeoView::Localized(languages) => {
+                map_geo_fields(value, &|geo| localize_geo(geo, languages))
+            }
+        };
+        match &self.fields {
+            Some(tree) => tree.select(&value),
+            None => value,
+        }
+    }
+}
+
+impl Default for Projection {
+    fn default() -> Projection {
+        Projection::new(None, GeoView::Compact)
+    }
+}
+
+impl FieldTree {
+    fn insert(&mut self, path: &[&str]) {
+        if self.whole {
+            return;
+        }
+        match path.split_first() {
+            Some((segment, rest)) => self
+                .children
+                .entry(segment.to_string())
+                .or_default()
+                .insert(rest),
+            None => {
+                self.whole = true;
+                self.children.clear();
+            }
+        }
+    }
+
+    fn select(&self, value: &Value) -> Value {
+        if self.whole {
+            return value.clone();
+        }
+        match value {
+            Value::Object(map) => {
+                let mut selected = Map::new();
+                for (key, subtree) in self.children.iter() {
+                    if let Some(v) = map.get(key) {
+                        selected.insert(key.clone(), subtree.select(v));
+                    }
+                }
+                Value::Object(selected)
+            }
+            Value::Array(values) => Value::Array(values.iter().map(|v| self.select(v)).collect()),
+            other => other.clone(),
+        }
+    }
+}
+
+// Besides geo, the MaxMind Country database section carries the same names maps and gets
+// the same view.
+fn map_geo_fields(value: Value, f: &dyn Fn(Value) -> Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| match key.as_str() {
+                    "geo" => (key, f(value)),
+                    "maxmind" => (key, map_maxmind_country(value, f)),
+                    _ => (key, map_geo_fields(value, f)),
+                })
+                .collect(),
+        ),
+        Value::Array(values) => {
+            Value::Array(values.into_iter().map(|v| map_geo_fields(v, f)).collect())
+        }
+        other => other,
+    }
+}
+
+fn map_maxmind_country(mut value: Value, f: &dyn Fn(Value) -> Value) -> Value {
+    if let Some(country) = value.get_mut("country") {
+        // Country records have no city, subdivision or location fields to show.
+        *country = match f(country.take()) {
+            Value::Object(map) => Value::Object(
+                map.into_iter()
+                    .filter(|(_, v)| !v.is_null() && v.as_array().map_or(true, |a| !a.is_empty()))
+                    .collect(),
+            ),
+            other => other,
+        };
+    }
+    value
+}
+
+fn localize_geo(value: Value, languages: &[String]) -> Value {
+    if value.is_null() {
+        return value;
+    }
+    match serde_json::from_value::<City>(value.clone()) {
+        Ok(city) => serde_json::to_value(LocalizedCity::new(&city, languages)).unwrap(),
+        Err(_) => value,
+    }
+}
+
+fn compact_geo(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut compact = Map::new();
+            for (key, value) in map.into_iter() {
+                match key.as_str() {
+                    "geoname_id" | "metro_code" => {}
+                    "names" => {
+                        let name = value.get("en").cloned().unwrap_or(Value::Null);
+                        compact.insert("name".to_owned(), name);
+                    }
+                    _ if value.is_null() => {}
+                    _ => {
+                        compact.insert(key, compact_geo(value));
+                    }
+                }
+            }
+            Value::Object(compact)
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(compact_geo).collect()),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn lookup() -> Value {
+        let country = json!({
+            "continent": { "code": "EU", "geoname_id": 6255148, "names": { "en": "Europe", "de": "Europa" } },
+            "country": { "geoname_id": 2921044, "iso_code": "DE", "names": { "en": "Germany", "de": "Deutschland" } },
+            "registered_country": { "geoname_id": 2921044, "iso_code": "DE", "names": { "en": "Germany", "de": "Deutschland" } }
+        });
+        json!({
+            "ip": "192.0.2.1",
+            "geo": country.clone(),
+            "maxmind": { "country": country, "asn": { "autonomous_system_number": 64496 } }
+        })
+    }
+
+    #[test]
+    fn compact_view_applies_to_maxmind_country() {
+        let value = Projection::new(None, GeoView::Compact).apply(lookup());
+        let expected = json!({
+            "continent": { "code": "EU", "name": "Europe" },
+            "country": { "iso_code": "DE", "name": "Germany" },
+            "registered_country": { "iso_code": "DE", "name": "Germany" }
+        });
+        assert_eq!(value["geo"], expected);
+        assert_eq!(value["maxmind"]["country"], expected);
+        assert_eq!(value["maxmind"]["asn"]["autonomous_system_number"], 64496);
+    }
+
+    #[test]
+    fn localized_view_applies_to_maxmind_country() {
+        let value = Projection::new(None, GeoView::localized("de")).apply(lookup());
+        let expected = json!({
+            "continent": "Europa",
+            "continent_code": "EU",
+            "country": "Deutschland",
+            "country_code": "DE",
+            "registered_country": "Deutschland",
+            "registered_country_code": "DE"
+        });
+        assert_eq!(value["maxmind"]["country"], expected);
+        assert_eq!(value["geo"]["country"], "Deutschland");
+    }
+
+    #[test]
+    fn full_view_keeps_names() {
+        let value = Projection::new(None, GeoView::Full).apply(lookup());
+        assert_eq!(value, lookup());
+    }
+}
@@ -0,0 +1,204 @@
+use std::net::IpAddr;
+
+use cidr::AnyIpCidr;
+use hyper::HeaderMap;
+
+pub fn client_ip(remote: IpAddr, headers: &HeaderMap, trusted_proxies: &[AnyIpCidr]) -> IpAddr {
+    let remote = canonical_ip(remote);
+    if !is_trusted(&remote, trusted_proxies) {
+        return remote;
+    }
+
+    // Walk the forwarding chain from the closest hop outwards and stop at the first
+    // address we don't trust, anything before it could have been forged by the client. A
+    // hop that can't be parsed (e.g. "unknown") ends the walk at the last trusted proxy.
+    let mut client = remote;
+    for ip in forwarded_chain(headers).into_iter().rev() {
+        match ip {
+            Some(ip) => {
+                client = ip;
+                if !is_trusted(&ip, trusted_proxies) {
+                    break;
+                }
+            }
+            None => break,
+        }
+    }
+    client
+}
+
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let forwarded = header_values(headers, "Forwarded");
+    if !forwarded.is_empty() {
+        return forwarded
+            .iter()
+            .flat_map(|value| value.split(','))
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.trim().split_once('='))
+                    .find(|(key, _)| key.eq_ignore_ascii_case("for"))
+                    .and_then(|(_, value)| parse_node(value))
+            })
+            .collect();
+    }
+
+    let forwarded_for = header_values(headers, "X-Forwarded-For");
+    if !forwarded_for.is_empty() {
+        return forwarded_for
+            .iter()
+            .flat_map(|value| value.split(','))
+            .map(parse_node)
+            .collect();
+    }
+
+    header_values(headers, "X-Real-IP")
+        .iter()
+        .map(|value| parse_node(value))
+        .collect()
+}
+
+fn header_values(headers: &HeaderMap, name: &str) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .map(|value| value.to_owned())
+        .collect()
+}
+
+fn parse_node(value: &str) -> Option<IpAddr> {
+    let value = value.trim().trim_matches('"');
+    let ip = if let Some(bracketed) = value.strip_prefix('[') {
+        bracketed.split(']').next()?.parse::<IpAddr>().ok()?
+    } else {
+        match value.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => value.rsplit_once(':')?.0.parse::<IpAddr>().ok()?,
+        }
+    };
+    Some(canonical_ip(ip))
+}
+
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        v4 => v4,
+    }
+}
+
+fn is_trusted(ip: &IpAddr, trusted_proxies: &[AnyIpCidr]) -> bool {
+    trusted_proxies.iter().any(|cidr| cidr.contains(ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_map(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    fn proxies() -> Vec<AnyIpCidr> {
+        vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "2001:db8::/32".parse().unwrap(),
+        ]
+    }
+
+    #[test]
+    fn untrusted_remote_ignores_headers() {
+        let headers = header_map(&[("X-Forwarded-For", "1.2.3.4")]);
+        assert_eq!(
+            client_ip(ip("198.51.100.7"), &headers, &proxies()),
+            ip("198.51.100.7")
+        );
+    }
+
+    #[test]
+    fn no_trusted_proxies_ignores_headers() {
+        let headers = header_map(&[("X-Forwarded-For", "1.2.3.4")]);
+        assert_eq!(client_ip(ip("10.0.0.1"), &headers, &[]), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn spoofed_entries_before_the_client_are_ignored() {
+        // The client sent "X-Forwarded-For: 6.6.6.6", the proxy appended the real address.
+        let headers = header_map(&[("X-Forwarded-For", "6.6.6.6, 203.0.113.9")]);
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), &headers, &proxies()),
+            ip("203.0.113.9")
+        );
+    }
+
+    #[test]
+    fn spoofed_trusted_address_does_not_skip_the_client() {
+        let headers = header_map(&[("X-Forwarded-For", "10.9.9.9, 203.0.113.9, 10.0.0.2")]);
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), &headers, &proxies()),
+            ip("203.0.113.9")
+        );
+    }
+
+    #[test]
+    fn all_trusted_chain_returns_the_first_hop() {
+        let headers = header_map(&[("X-Forwarded-For", "10.0.0.3, 10.0.0.2")]);
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), &headers, &proxies()),
+            ip("10.0.0.3")
+        );
+    }
+
+    #[test]
+    fn unparseable_hop_stops_the_walk() {
+        let headers = header_map(&[("X-Forwarded-For", "6.6.6.6, unknown, 10.0.0.2")]);
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), &headers, &proxies()),
+            ip("10.0.0.2")
+        );
+    }
+
+    #[test]
+    fn forwarded_takes_precedence() {
+        let headers = header_map(&[
+            ("Forwarded", "for=198.51.100.1;proto=https, for=203.0.113.9"),
+            ("X-Forwarded-For", "6.6.6.6"),
+            ("X-Real-IP", "7.7.7.7"),
+        ]);
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), &headers, &proxies()),
+            ip("203.0.113.9")
+        );
+    }
+
+    #[test]
+    fn forwarded_ipv6_with_port() {
+        let headers = header_map(&[("Forwarded", "for=\"[2001:db9::1]:4711\"")]);
+        assert_eq!(
+            client_ip(ip("2001:db8::1"), &headers, &proxies()),
+            ip("2001:db9::1")
+        );
+    }
+
+    #[test]
+    fn ipv4_with_port_and_mapped_addresses() {
+        let headers = header_map(&[("X-Forwarded-For", "203.0.113.9:5555")]);
+        assert_eq!(
+            client_ip(ip("::ffff:10.0.0.1"), &headers, &proxies()),
+            ip("203.0.113.9")
+        );
+        let headers = header_map(&[("X-Real-IP", "::ffff:203.0.113.9")]);
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), &headers, &proxies()),
+            ip("203.0.113.9")
+        );
+    }
+}
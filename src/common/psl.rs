@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Result};
+use std::path::Path;
+
+use crate::idn;
+use crate::logging;
+use crate::status::{self, DataFileInfo};
+
+static PRIVATE_SECTION_BEGIN: &'static str = "// ===BEGIN PRIVATE DOMAINS===";
+static PRIVATE_SECTION_END: &'static str = "// ===END PRIVATE DOMAINS===";
+
+pub fn load_public_suffix_list(file_path: &Path) -> Result<PublicSuffixList> {
+    let file = File::open(file_path)?;
+    let mut rules = HashMap::new();
+    let mut exceptions = HashMap::new();
+    let mut private = false;
+
+    for line_res in BufReader::new(file).lines() {
+        if line_res.is_ok() {
+            let line = line_res.unwrap();
+            let rule = line.split_whitespace().next().unwrap_or("");
+            if line.starts_with(PRIVATE_SECTION_BEGIN) {
+                private = true;
+            } else if line.starts_with(PRIVATE_SECTION_END) {
+                private = false;
+            } else if rule.is_empty() || rule.starts_with("//") {
+                continue;
+            } else if rule.starts_with('!') {
+                exceptions.insert(normalize_rule(&rule[1..]), private);
+            } else {
+                rules.insert(normalize_rule(rule), private);
+            }
+        } else {
+            logging::warn("skipping non utf8 line", &[("file", &file_path.display())]);
+        }
+    }
+
+    let mut info = status::create_data_file_info("public-suffix-list", file_path);
+    info.records = Some(rules.len() + exceptions.len());
+    Ok(PublicSuffixList {
+        rules,
+        exceptions,
+        info,
+    })
+}
+
+fn normalize_rule(rule: &str) -> String {
+    if rule.starts_with("*.") {
+        format!(
+            "*.{}",
+            idn::to_ascii(&rule[2..]).unwrap_or(rule[2..].to_owned())
+        )
+    } else {
+        idn::to_ascii(rule).unwrap_or(rule.to_owned())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PublicSuffixList {
+    rules: HashMap<String, bool>,
+    exceptions: HashMap<String, bool>,
+    info: DataFileInfo,
+}
+
+impl PublicSuffixList {
+    pub fn info(&self) -> &DataFileInfo {
+        &self.info
+    }
+
+    pub fn lookup(&self, domain: &str) -> Option<SuffixLookupResult> {
+        let domain = idn::to_ascii(domain.trim_end_matches('.'))?;
+        let labels: Vec<&str> = domain.split('.').collect();
+        if labels.iter().any(|l| l.is_empty()) {
+            return None;
+        }
+
+        let (suffix_idx, private) = self.find_suffix(&labels);
+        let registrable_domain = if suffix_idx > 0 {
+            Some(labels[suffix_idx - 1..].join("."))
+        } else {
+            None
+        };
+
+        Some(SuffixLookupResult {
+            registrable_domain,
+            public_suffix: labels[suffix_idx..].join("."),
+            is_private_suffix: private,
+        })
+    }
+
+    fn find_suffix(&self, labels: &[&str]) -> (usize, bool) {
+        for idx in 0..labels.len() {
+            let candidate = labels[idx..].join(".");
+            if let Some(private) = self.exceptions.get(&candidate) {
+                return (idx + 1, *private);
+            }
+            if let Some(private) = self.rules.get(&candidate) {
+                return (idx, *private);
+            }
+            if idx + 1 < labels.len() {
+                let wildcard = format!("*.{}", labels[idx + 1..].join("."));
+                if let Some(private) = self.rules.get(&wildcard) {
+                    return (idx, *private);
+                }
+            }
+        }
+        (labels.len() - 1, false)
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SuffixLookupResult {
+    pub registrable_domain: Option<String>,
+    pub public_suffix: String,
+    pub is_private_suffix: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    static RULES: &'static str = "// ===BEGIN ICANN DOMAINS===
+com
+uk
+co.uk
+*.ck
+!www.ck
+// ===END ICANN DOMAINS===
+// ===BEGIN PRIVATE DOMAINS===
+blogspot.com
+// ===END PRIVATE DOMAINS===
+";
+
+    fn load() -> PublicSuffixList {
+        static LOADED: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "net-lookup-psl-{}-{}.dat",
+            std::process::id(),
+            LOADED.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&path, RULES).unwrap();
+        let list = load_public_suffix_list(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        list
+    }
+
+    fn lookup(list: &PublicSuffixList, domain: &str) -> (Option<String>, String, bool) {
+        let result = list.lookup(domain).unwrap();
+        (
+            result.registrable_domain,
+            result.public_suffix,
+            result.is_private_suffix,
+        )
+    }
+
+    fn some(domain: &str) -> Option<String> {
+        Some(domain.to_owned())
+    }
+
+    #[test]
+    fn plain_rules() {
+        let list = load();
+        let expected = (some("example.co.uk"), "co.uk".to_owned(), false);
+        assert_eq!(lookup(&list, "www.example.co.uk"), expected);
+        let expected = (some("example.com"), "com".to_owned(), false);
+        assert_eq!(lookup(&list, "example.com"), expected);
+    }
+
+    #[test]
+    fn wildcard_rules() {
+        let list = load();
+        let expected = (some("shop.foo.ck"), "foo.ck".to_owned(), false);
+        assert_eq!(lookup(&list, "www.shop.foo.ck"), expected);
+        assert_eq!(lookup(&list, "foo.ck"), (None, "foo.ck".to_owned(), false));
+    }
+
+    #[test]
+    fn exception_rules() {
+        let list = load();
+        let expected = (some("www.ck"), "ck".to_owned(), false);
+        assert_eq!(lookup(&list, "www.ck"), expected);
+        assert_eq!(lookup(&list, "a.www.ck"), expected);
+    }
+
+    #[test]
+    fn private_section() {
+        let list = load();
+        let expected = (some("foo.blogspot.com"), "blogspot.com".to_owned(), true);
+        assert_eq!(lookup(&list, "www.foo.blogspot.com"), expected);
+        assert_eq!(
+            lookup(&list, "blogspot.com"),
+            (None, "blogspot.com".to_owned(), true)
+        );
+    }
+
+    #[test]
+    fn bare_suffix_has_no_registrable_domain() {
+        let list = load();
+        assert_eq!(lookup(&list, "co.uk"), (None, "co.uk".to_owned(), false));
+        assert_eq!(lookup(&list, "com"), (None, "com".to_owned(), false));
+    }
+
+    #[test]
+    fn unlisted_tld_uses_the_implicit_rule() {
+        let list = load();
+        let expected = (some("example.zzz"), "zzz".to_owned(), false);
+        assert_eq!(lookup(&list, "www.example.zzz"), expected);
+        assert_eq!(lookup(&list, "zzz"), (None, "zzz".to_owned(), false));
+    }
+
+    #[test]
+    fn trailing_dot_and_upper_case() {
+        let list = load();
+        let expected = (some("example.co.uk"), "co.uk".to_owned(), false);
+        assert_eq!(lookup(&list, "WWW.Example.CO.UK."), expected);
+        assert!(list.lookup("example..com").is_none());
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use futures::future::{self, Either};
+use futures::FutureExt;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+
+use crate::logging;
+use crate::lookup::LookupHandler;
+
+// Counts the SIGHUPs received so far, every consumer gets its own receiver and reacts to
+// changes of the count.
+pub fn listen_for_sighup() -> watch::Receiver<u64> {
+    let (sender, receiver) = watch::channel(0);
+    match signal(SignalKind::hangup()) {
+        Ok(mut hangup) => {
+            tokio::spawn(async move {
+                let mut count = 0;
+                while hangup.recv().await.is_some() {
+                    count += 1;
+                    if sender.send(count).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        Err(e) => logging::warn("unable to listen for SIGHUP", &[("error", &e)]),
+    }
+    receiver
+}
+
+// Resolves on the next SIGHUP, never if signals could not be installed.
+pub async fn next_sighup(sighup: &mut watch::Receiver<u64>) {
+    if sighup.changed().await.is_err() {
+        future::pending::<()>().await;
+    }
+}
+
+// Reloads the data files on SIGHUP and, when an interval is given, whenever their
+// modification times change. A change is only picked up once the times have been stable
+// for a full interval so files still being written by the updater are not loaded half way,
+// and a set of files that failed to load is not retried until it changes again.
+pub fn watch_data_files(
+    handler: LookupHandler,
+    interval: Option<Duration>,
+    mut sighup: watch::Receiver<u64>,
+) {
+    tokio::spawn(async move {
+        let mut pending = None;
+        let mut failed = None;
+        loop {
+            let check = match interval {
+                Some(interval) => tokio::time::sleep(interval).boxed(),
+                None => future::pending().boxed(),
+            };
+            if let Either::Left(_) = future::select(Box::pin(next_sighup(&mut sighup)), check).await
+            {
+                logging::info("received SIGHUP, reloading data files", &[]);
+                let _ = reload_data_files(&handler).await;
+                pending = None;
+                continue;
+            }
+
+            let modified = handler.data_files_modified();
+            if !handler.data_files_changed(&modified) || failed.as_ref() == Some(&modified) {
+                pending = None;
+            } else if pending.as_ref() == Some(&modified) {
+                logging::info("data files changed, reloading", &[]);
+                if reload_data_files(&handler).await.is_err() {
+                    failed = Some(modified);
+                }
+                pending = None;
+            } else {
+                logging::debug(
+                    "data files changed, waiting for them to settle",
+                    &[("interval_secs", &interval.unwrap_or_default().as_secs())],
+                );
+                pending = Some(modified);
+            }
+        }
+    });
+}
+
+// Loading the data files blocks for a while, keep it off the runtime threads.
+pub async fn reload_data_files(handler: &LookupHandler) -> Result<(), String> {
+    let handler = handler.clone();
+    tokio::task::spawn_blocking(move || handler.reload())
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()))
+}
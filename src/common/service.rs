@@ -1,20 +1,260 @@
 use std::convert::Infallible;
 use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
+use cidr::AnyIpCidr;
+use futures::future::{self, Either};
+use futures::{stream, StreamExt};
 use hyper;
+use hyper::body::HttpBody;
+use hyper::header::HeaderValue;
 use hyper::http::Result;
-use hyper::server::conn::AddrStream;
-use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Request, Response, Server};
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Request, Response};
 use hyper::{Method, StatusCode};
 use serde_json;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, watch};
+use tokio_rustls::TlsAcceptor;
 
+use crate::access::{self, AccessControl, AccessDenied};
+use crate::batch;
+use crate::cors::{self, CorsOptions};
+use crate::format::{self, Formattable, OutputFormat};
+use crate::geo::GeoPoint;
+use crate::idn;
+use crate::listener::{self, BoundListener, Connection, Listener};
+use crate::logging;
 use crate::lookup;
-use lookup::LookupHandler;
+use crate::metrics::Metrics;
+use crate::projection::{GeoView, Projection};
+use crate::proxy;
+use crate::reload;
+use crate::shutdown::ShutdownHandle;
+use crate::status::DisabledDataSource;
+use crate::tls::{self, TlsOptions};
+use lookup::{DomainLookupOptions, LookupHandler};
+
+// Upper bound for a single batch query including its JSON quoting and separator, used
+// with the maximum batch size to cap request bodies.
+static MAX_QUERY_BYTES: usize = 512;
+static ACCEPT_ERROR_DELAY: Duration = Duration::from_secs(1);
 
 #[derive(Clone)]
 pub struct LookupContext {
     handler: LookupHandler,
+    max_batch_size: usize,
+    parallelism: usize,
+    max_data_age: Duration,
+    legacy_routes: bool,
+    admin_routes: bool,
+    trusted_proxies: Arc<Vec<AnyIpCidr>>,
+    access: Arc<AccessControl>,
+    metrics: Arc<Metrics>,
+    shutdown: ShutdownHandle,
+    cors: Option<Arc<CorsOptions>>,
+    cache_max_age: Duration,
+    required_sources: Arc<Vec<String>>,
+    unix_socket: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Route {
+    Ip(String),
+    Domain(String),
+    Asn(String),
+    Prefix(String),
+    Me,
+    Lookup,
+    Status,
+    Health,
+    Ready,
+    Metrics,
+    AdminReload,
+    Legacy(String),
+}
+
+impl Route {
+    fn from_path(path: &str, legacy_routes: bool, admin_routes: bool) -> Option<Route> {
+        let path = path.trim_start_matches('/');
+        let (name, value) = match path.split_once('/') {
+            Some((name, value)) => (name, Some(percent_decode(value, false))),
+            None => (path, None),
+        };
+        match (name, value) {
+            ("ip", Some(value)) => Some(Route::Ip(value)),
+            ("domain", Some(value)) => Some(Route::Domain(value)),
+            ("asn", Some(value)) => Some(Route::Asn(value)),
+            ("prefix", Some(value)) => Some(Route::Prefix(value)),
+            ("", None) | ("me", None) => Some(Route::Me),
+            ("lookup", None) => Some(Route::Lookup),
+            ("status", None) => Some(Route::Status),
+            ("health", None) => Some(Route::Health),
+            ("health", Some(value)) if value == "live" => Some(Route::Health),
+            ("health", Some(value)) if value == "ready" => Some(Route::Ready),
+            ("metrics", None) => Some(Route::Metrics),
+            ("admin", Some(value)) if admin_routes && value == "reload" => Some(Route::AdminReload),
+            (name, None) if legacy_routes && !name.is_empty() => {
+                Some(Route::Legacy(percent_decode(name, false)))
+            }
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Route::Ip(_) => "ip",
+            Route::Domain(_) => "domain",
+            Route::Asn(_) => "asn",
+            Route::Prefix(_) => "prefix",
+            Route::Me => "me",
+            Route::Lookup => "lookup",
+            Route::Status => "status",
+            Route::Health => "health",
+            Route::Ready => "ready",
+            Route::Metrics => "metrics",
+            Route::AdminReload => "admin_reload",
+            Route::Legacy(_) => "legacy",
+        }
+    }
+
+    fn method(&self) -> Method {
+        match self {
+            Route::Lookup | Route::AdminReload => Method::POST,
+            _ => Method::GET,
+        }
+    }
+}
+
+async fn handle_request(
+    context: LookupContext,
+    addr: SocketAddr,
+    req: Request<Body>,
+) -> Result<Response<Body>> {
+    let started = Instant::now();
+    let metrics = context.metrics.clone();
+    let route = Route::from_path(
+        req.uri().path(),
+        context.legacy_routes,
+        context.admin_routes,
+    )
+    .map(|route| route.name())
+    .unwrap_or("unknown");
+
+    let request_id = get_request_id(&req);
+    let client_ip = proxy::client_ip(addr.ip(), req.headers(), &context.trusted_proxies);
+    let method = req.method().clone();
+    let query = access::redact_query(req.uri());
+    let origin = req.headers().get("Origin").cloned();
+    let conditional = req.headers().contains_key("If-None-Match");
+    let cors = context.cors.clone();
+    let preflight = cors.is_some() && cors::is_preflight(&req);
+    let access = match route {
+        _ if preflight => Ok(None),
+        "health" | "ready" => Ok(None),
+        _ => context.access.check(
+            &req,
+            client_ip,
+            route == "admin_reload",
+            context.unix_socket,
+        ),
+    };
+    let api_key = match &access {
+        Ok(key) => key.clone(),
+        Err(AccessDenied::RateLimited { key, .. }) => key.clone(),
+        Err(_) => None,
+    };
+    let context = LookupContext {
+        handler: context.handler.with_request_id(&request_id),
+        ..context
+    };
+
+    let in_flight = metrics.start_request();
+    let mut response = match (&cors, access) {
+        (Some(cors), _) if preflight => Ok(cors::preflight_response(cors, &req)),
+        (_, Ok(_)) => handle_lookup(context, addr, req).await,
+        (_, Err(denied)) => access_denied_response(&denied, &metrics),
+    };
+    let status = match &mut response {
+        Ok(response) => {
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                response.headers_mut().insert("X-Request-Id", value);
+            }
+            if let (Some(cors), false) = (&cors, preflight) {
+                cors::add_cors_headers(cors, origin.as_ref(), response);
+            }
+            response.status().as_u16()
+        }
+        Err(_) => 500,
+    };
+    let elapsed = started.elapsed();
+    drop(in_flight);
+    metrics.finish_request(route, status, elapsed);
+    let cache_hit = conditional && status == 304;
+    if conditional {
+        metrics.record_cache_request(route, cache_hit);
+    }
+    if let Some(key) = &api_key {
+        metrics.record_api_key_request(key, status);
+    }
+    logging::info(
+        "request",
+        &[
+            ("request_id", &request_id),
+            ("remote_addr", &addr),
+            ("client_ip", &client_ip),
+            ("api_key", &api_key.as_deref().unwrap_or("-")),
+            ("method", &method),
+            ("route", &route),
+            ("query", &query),
+            ("status", &status),
+            ("cache_hit", &cache_hit),
+            (
+                "latency_ms",
+                &format!("{:.3}", elapsed.as_secs_f64() * 1000.0),
+            ),
+        ],
+    );
+    response
+}
+
+// Data files are loaded before the listeners start, so a serving instance is ready unless
+// it is draining. Optional sources that are missing don't count, only those the operator
+// listed with --require-source keep it out of rotation.
+fn readiness(
+    draining: bool,
+    disabled: &[DisabledDataSource],
+    required_sources: &[String],
+) -> (StatusCode, serde_json::Value) {
+    let missing: Vec<&str> = disabled
+        .iter()
+        .map(|source| source.name.as_str())
+        .filter(|name| required_sources.iter().any(|required| required == name))
+        .collect();
+    if draining {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            serde_json::json!({ "status": "draining" }),
+        )
+    } else if !missing.is_empty() {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            serde_json::json!({ "status": "degraded", "disabled": missing }),
+        )
+    } else {
+        (StatusCode::OK, serde_json::json!({ "status": "ready" }))
+    }
+}
+
+fn get_request_id(req: &Request<Body>) -> String {
+    req.headers()
+        .get("X-Request-Id")
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 128 && id.chars().all(|c| c.is_ascii_graphic()))
+        .map(|id| id.to_owned())
+        .unwrap_or_else(logging::create_request_id)
 }
 
 async fn handle_lookup(
@@ -22,52 +262,719 @@ async fn handle_lookup(
     addr: SocketAddr,
     req: Request<Body>,
 ) -> Result<Response<Body>> {
-    match (req.method(), req.uri().path()) {
-        (&Method::GET, path) => {
-            let ip_result = path.trim_start_matches('/').parse::<IpAddr>();
-            if ip_result.is_ok() {
-                let result = context.handler.lookup_ip(ip_result.unwrap()).await;
+    let route = match Route::from_path(
+        req.uri().path(),
+        context.legacy_routes,
+        context.admin_routes,
+    ) {
+        Some(route) => route,
+        None => {
+            return error_response(
+                StatusCode::NOT_FOUND,
+                format!("no route for {}", req.uri().path()),
+            )
+        }
+    };
+    if req.method() != route.method() {
+        let mut response = error_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            format!("{} is not allowed for {}", req.method(), req.uri().path()),
+        )?;
+        response
+            .headers_mut()
+            .insert("Allow", route.method().as_str().parse().unwrap());
+        return Ok(response);
+    }
+
+    match route {
+        Route::Lookup => handle_batch_lookup(context, req).await,
+        Route::Status => {
+            let status = context.handler.data_status(context.max_data_age);
+            Response::builder()
+                .header("Content-Type", "application/json")
+                .body(Body::from(serde_json::to_string(&status).unwrap()))
+        }
+        Route::Metrics => {
+            let status = context.handler.data_status(context.max_data_age);
+            Response::builder()
+                .header("Content-Type", "text/plain; version=0.0.4")
+                .body(Body::from(context.metrics.render(&status)))
+        }
+        Route::AdminReload => match reload::reload_data_files(&context.handler).await {
+            Ok(()) => {
+                let status = context.handler.data_status(context.max_data_age);
                 Response::builder()
                     .header("Content-Type", "application/json")
-                    .body(Body::from(serde_json::to_string(&result).unwrap()))
-            } else {
+                    .body(Body::from(serde_json::to_string(&status).unwrap()))
+            }
+            Err(err) => error_response(StatusCode::CONFLICT, err),
+        },
+        Route::Health => Response::builder()
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                serde_json::json!({ "status": "ok" }).to_string(),
+            )),
+        Route::Ready => {
+            let status = context.handler.data_status(context.max_data_age);
+            let (status, body) = readiness(
+                context.shutdown.is_draining(),
+                &status.disabled,
+                &context.required_sources,
+            );
+            Response::builder()
+                .status(status)
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+        }
+        Route::Me => {
+            let ip = proxy::client_ip(addr.ip(), req.headers(), &context.trusted_proxies);
+            let result = context.handler.lookup_ip(ip).await;
+            let cache_control = cache_control(&context, false, true);
+            format_response(&req, &result, cache_control)
+        }
+        Route::Ip(value) => match value.parse::<IpAddr>() {
+            Ok(ip) => {
+                let result = context.handler.lookup_ip(ip).await;
+                let cache_control = cache_control(&context, false, false);
+                format_response(&req, &result, cache_control)
+            }
+            Err(_) => error_response(
+                StatusCode::BAD_REQUEST,
+                format!("'{}' is not a valid ip address", value),
+            ),
+        },
+        Route::Domain(value) => {
+            if idn::to_ascii(&value).is_none() {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    format!("'{}' is not a valid domain", value),
+                );
+            }
+            let options = DomainLookupOptions {
+                detect_wildcard: is_flag_set(&req, "wildcard"),
+            };
+            let result = context
+                .handler
+                .lookup_domain_with_options(value, &options)
+                .await;
+            let cache_control = cache_control(&context, true, false);
+            format_response(&req, &result, cache_control)
+        }
+        Route::Asn(value) => {
+            let id = value
+                .strip_prefix("AS")
+                .or_else(|| value.strip_prefix("as"))
+                .unwrap_or(&value)
+                .parse::<u32>();
+            match id {
+                Ok(id) => {
+                    let result = context.handler.lookup_asn(id);
+                    if result.is_empty() {
+                        error_response(StatusCode::NOT_FOUND, format!("AS{} not found", id))
+                    } else {
+                        let cache_control = cache_control(&context, false, false);
+                        format_response(&req, &result, cache_control)
+                    }
+                }
+                Err(_) => error_response(
+                    StatusCode::BAD_REQUEST,
+                    format!("'{}' is not a valid as number", value),
+                ),
+            }
+        }
+        Route::Prefix(value) => match value.parse::<AnyIpCidr>() {
+            Ok(prefix) if prefix != AnyIpCidr::Any => {
+                let result = context.handler.lookup_prefix(prefix);
+                let cache_control = cache_control(&context, false, false);
+                format_response(&req, &result, cache_control)
+            }
+            _ => error_response(
+                StatusCode::BAD_REQUEST,
+                format!("'{}' is not a valid ip prefix", value),
+            ),
+        },
+        Route::Legacy(value) => match value.parse::<IpAddr>() {
+            Ok(ip) => {
+                let result = context.handler.lookup_ip(ip).await;
+                let cache_control = cache_control(&context, false, false);
+                format_response(&req, &result, cache_control)
+            }
+            Err(_) => {
+                let options = DomainLookupOptions {
+                    detect_wildcard: is_flag_set(&req, "wildcard"),
+                };
                 let result = context
                     .handler
-                    .lookup_domain(path.trim_start_matches('/').to_owned())
+                    .lookup_domain_with_options(value, &options)
                     .await;
-                Response::builder()
-                    .header("Content-Type", "application/json")
-                    .body(Body::from(serde_json::to_string(&result).unwrap()))
+                let cache_control = cache_control(&context, true, false);
+                format_response(&req, &result, cache_control)
             }
+        },
+    }
+}
+
+fn format_response<T: Formattable>(
+    req: &Request<Body>,
+    item: &T,
+    cache_control: String,
+) -> Result<Response<Body>> {
+    let output_format = get_output_format(req);
+    let projection = get_projection(req);
+    if let Err(err) = output_format.check_fields(&projection) {
+        return error_response(StatusCode::BAD_REQUEST, err);
+    }
+    let body = format::format_document(item, output_format, &projection);
+    let etag = format!("\"{:016x}\"", fnv1a(body.as_bytes()));
+    let builder = Response::builder()
+        .header("Cache-Control", cache_control)
+        .header("ETag", &etag)
+        .header("Vary", "Accept, Accept-Language");
+    if is_not_modified(req, &etag) {
+        return builder.status(StatusCode::NOT_MODIFIED).body(Body::empty());
+    }
+    builder
+        .header("Content-Type", output_format.content_type())
+        .body(Body::from(body))
+}
+
+// Data file results use the usual heuristic of a tenth of the time since the data last
+// changed, results that include dns answers are further limited to their lowest ttl. Both
+// are capped by the configured maximum and responses are private when they depend on the
+// caller.
+fn cache_control(context: &LookupContext, dns: bool, per_client: bool) -> String {
+    if dns && context.handler.dns_failed() {
+        return "no-cache".to_owned();
+    }
+    let data_max_age = context
+        .handler
+        .data_last_modified()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .map(|age| age.as_secs() / 10)
+        .unwrap_or(0);
+    let max_age = match context.handler.min_dns_ttl() {
+        Some(ttl) if dns => data_max_age.min(ttl as u64),
+        _ => data_max_age,
+    };
+    let scope = if per_client || context.access.requires_key() {
+        "private"
+    } else {
+        "public"
+    };
+    format!(
+        "{}, max-age={}",
+        scope,
+        max_age.min(context.cache_max_age.as_secs())
+    )
+}
+
+fn is_not_modified(req: &Request<Body>, etag: &str) -> bool {
+    req.headers()
+        .get_all("If-None-Match")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Reads the body until it exceeds the limit, returning None instead of buffering the rest.
+async fn read_body(
+    mut body: Body,
+    limit: usize,
+) -> std::result::Result<Option<Vec<u8>>, hyper::Error> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > limit {
+            return Ok(None);
         }
-        _ => {
-            let mut not_found = Response::default();
-            *not_found.status_mut() = StatusCode::NOT_FOUND;
-            Ok(not_found)
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes))
+}
+
+fn body_too_large_response(limit: usize) -> Result<Response<Body>> {
+    error_response(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!("request body exceeds the maximum of {} bytes", limit),
+    )
+}
+
+async fn handle_batch_lookup(context: LookupContext, req: Request<Body>) -> Result<Response<Body>> {
+    let options = DomainLookupOptions {
+        detect_wildcard: is_flag_set(&req, "wildcard"),
+    };
+    let output_format = get_output_format(&req);
+    let projection = get_projection(&req);
+    if let Err(err) = output_format.check_fields(&projection) {
+        return error_response(StatusCode::BAD_REQUEST, err);
+    }
+    let streaming = is_flag_set(&req, "stream") || output_format == OutputFormat::Ndjson;
+    let near = match get_query_param(&req, "near").map(|n| GeoPoint::parse(&n)) {
+        Some(Ok(point)) => Some(point),
+        Some(Err(err)) => return error_response(StatusCode::BAD_REQUEST, err),
+        None => None,
+    };
+    let radius_km = match get_query_param(&req, "radius_km").map(|r| r.parse::<f64>()) {
+        Some(Ok(radius)) if near.is_some() && radius >= 0.0 => Some(radius),
+        Some(_) => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "radius_km must be a non-negative number used together with near".to_owned(),
+            )
+        }
+        None => None,
+    };
+
+    let max_body_bytes = context
+        .max_batch_size
+        .saturating_mul(MAX_QUERY_BYTES)
+        .saturating_add(4096);
+    let content_length = req
+        .headers()
+        .get("Content-Length")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if content_length.is_some_and(|length| length > max_body_bytes) {
+        return body_too_large_response(max_body_bytes);
+    }
+    let body = match read_body(req.into_body(), max_body_bytes).await {
+        Ok(Some(body)) => body,
+        Ok(None) => return body_too_large_response(max_body_bytes),
+        Err(err) => {
+            return error_response(StatusCode::BAD_REQUEST, format!("{}", err));
+        }
+    };
+    let queries = match batch::parse_batch_body(&body) {
+        Ok(queries) => queries,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+    if queries.len() > context.max_batch_size {
+        return error_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "batch of {} queries exceeds the maximum of {}",
+                queries.len(),
+                context.max_batch_size
+            ),
+        );
+    }
+
+    let parallelism = context.parallelism;
+    let results = stream::iter(queries)
+        .map(move |query| {
+            let handler = context.handler.clone();
+            let options = options.clone();
+            async move { batch::lookup_batch_item(&handler, query, &options).await }
+        })
+        .buffered(parallelism)
+        .filter_map(move |item| {
+            let item = match near.as_ref() {
+                Some(near) => batch::filter_near(item, near, radius_km),
+                None => Some(item),
+            };
+            future::ready(item)
+        });
+
+    if streaming {
+        let output_format = match output_format {
+            OutputFormat::Json | OutputFormat::PrettyJson => OutputFormat::Ndjson,
+            other => other,
+        };
+        let header = stream::iter(output_format.header(&projection));
+        let items =
+            results.map(move |result| format::format_item(&result, output_format, &projection));
+        let chunks = header.chain(items).map(Ok::<_, Infallible>);
+        Response::builder()
+            .header("Content-Type", output_format.content_type())
+            .body(Body::wrap_stream(chunks))
+    } else {
+        let results: Vec<_> = results.collect().await;
+        Response::builder()
+            .header("Content-Type", output_format.content_type())
+            .body(Body::from(format::format_list(
+                &results,
+                output_format,
+                &projection,
+            )))
+    }
+}
+
+fn error_response(status: StatusCode, message: String) -> Result<Response<Body>> {
+    let body = serde_json::json!({ "error": message });
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+}
+
+fn access_denied_response(denied: &AccessDenied, metrics: &Metrics) -> Result<Response<Body>> {
+    match denied {
+        AccessDenied::MissingKey => unauthorized_response("an api key is required"),
+        AccessDenied::InvalidKey => unauthorized_response("invalid api key"),
+        AccessDenied::NotAdmin => error_response(
+            StatusCode::FORBIDDEN,
+            "this endpoint requires an admin api key or a unix socket connection".to_owned(),
+        ),
+        AccessDenied::RateLimited {
+            limit, retry_after, ..
+        } => {
+            metrics.record_rate_limited(limit);
+            let retry_after = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            let mut response = error_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("{} rate limit exceeded, retry in {}s", limit, retry_after),
+            )?;
+            response
+                .headers_mut()
+                .insert("Retry-After", HeaderValue::from(retry_after));
+            Ok(response)
+        }
+    }
+}
+
+fn unauthorized_response(message: &str) -> Result<Response<Body>> {
+    let mut response = error_response(StatusCode::UNAUTHORIZED, message.to_owned())?;
+    response
+        .headers_mut()
+        .insert("WWW-Authenticate", HeaderValue::from_static("Bearer"));
+    Ok(response)
+}
+
+fn get_output_format(req: &Request<Body>) -> OutputFormat {
+    get_query_param(req, "format")
+        .and_then(|name| OutputFormat::from_name(&name))
+        .or_else(|| {
+            req.headers()
+                .get_all("Accept")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .find_map(OutputFormat::from_accept)
+        })
+        .unwrap_or(OutputFormat::Json)
+}
+
+// Geo names are only localized when asked for with ?lang=, a bare ?lang picks the
+// languages from the Accept-Language header.
+// An explicit ?lang wins over the Accept-Language header, geo names stay in the compact
+// English view only when neither is given.
+fn get_projection(req: &Request<Body>) -> Projection {
+    let accept_language = req
+        .headers()
+        .get("Accept-Language")
+        .and_then(|value| value.to_str().ok());
+    let geo_view = match (
+        get_query_param(req, "geo").as_deref(),
+        get_query_param(req, "lang").filter(|lang| !lang.is_empty()),
+        accept_language,
+    ) {
+        (Some("full"), _, _) => GeoView::Full,
+        (_, Some(lang), _) => GeoView::localized(&lang),
+        (_, None, Some(languages)) => GeoView::localized(languages),
+        _ => GeoView::Compact,
+    };
+    Projection::new(get_query_param(req, "fields").as_deref(), geo_view)
+}
+
+fn get_query_param(req: &Request<Body>, key: &str) -> Option<String> {
+    req.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .map(|pair| pair.splitn(2, '=').collect::<Vec<_>>())
+            .find(|kv| kv[0] == key)
+            .map(|kv| percent_decode(kv.get(1).unwrap_or(&""), true))
+    })
+}
+
+fn percent_decode(value: &str, plus_as_space: bool) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let hex = bytes
+            .get(idx + 1..idx + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[idx], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                idx += 3;
+            }
+            (b'+', _) if plus_as_space => {
+                decoded.push(b' ');
+                idx += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                idx += 1;
+            }
         }
     }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn is_flag_set(req: &Request<Body>, key: &str) -> bool {
+    match get_query_param(req, key) {
+        Some(value) => value.is_empty() || value == "true" || value == "1",
+        None => false,
+    }
 }
 
 pub struct LookupService {
     pub handler: LookupHandler,
+    pub max_batch_size: usize,
+    pub parallelism: usize,
+    pub max_data_age: Duration,
+    pub legacy_routes: bool,
+    pub admin_routes: bool,
+    pub trusted_proxies: Vec<AnyIpCidr>,
+    pub access: Arc<AccessControl>,
+    pub metrics: Arc<Metrics>,
+    pub tls: Option<TlsOptions>,
+    pub socket_mode: u32,
+    pub cors: Option<CorsOptions>,
+    pub cache_max_age: Duration,
+    pub required_sources: Vec<String>,
+    pub shutdown: ShutdownHandle,
+    pub drain_timeout: Duration,
+    pub sighup: watch::Receiver<u64>,
 }
 
 impl LookupService {
-    pub async fn start(&self, host: IpAddr, port: u16) {
-        let addr = SocketAddr::new(host, port);
-        let make_service = make_service_fn(move |conn: &AddrStream| {
-            let context = LookupContext {
-                handler: self.handler.clone(),
+    // Binds every listener before serving so a bad address fails startup instead of
+    // leaving the daemon half up.
+    pub async fn start(&self, listeners: &[Listener]) -> std::result::Result<(), String> {
+        let acceptor = match &self.tls {
+            Some(tls) => Some(tls::watch_tls_acceptor(tls, self.sighup.clone())?),
+            None => None,
+        };
+        let mut bound = Vec::with_capacity(listeners.len());
+        for listener in listeners {
+            let listening = listener
+                .bind(self.socket_mode)
+                .await
+                .map_err(|e| format!("unable to listen on {}: {}", listener, e))?;
+            logging::info(
+                "running lookup service",
+                &[
+                    ("listen", listener),
+                    (
+                        "tls",
+                        &(acceptor.is_some() && matches!(listener, Listener::Tcp(_))),
+                    ),
+                ],
+            );
+            bound.push(listening);
+        }
+
+        // Every connection task holds a sender, the channel closes once all of them finished.
+        let (active, mut drained) = mpsc::channel::<()>(1);
+        future::join_all(
+            bound.into_iter().map(|listener| {
+                self.accept_connections(listener, acceptor.clone(), active.clone())
+            }),
+        )
+        .await;
+
+        drop(active);
+        logging::info("draining connections", &[]);
+        if tokio::time::timeout(self.drain_timeout, drained.recv())
+            .await
+            .is_err()
+        {
+            logging::warn("drain timeout reached, closing remaining connections", &[]);
+        }
+        Ok(())
+    }
+
+    fn create_context(&self) -> LookupContext {
+        LookupContext {
+            handler: self.handler.clone(),
+            max_batch_size: self.max_batch_size,
+            parallelism: self.parallelism,
+            max_data_age: self.max_data_age,
+            legacy_routes: self.legacy_routes,
+            admin_routes: self.admin_routes,
+            trusted_proxies: Arc::new(self.trusted_proxies.clone()),
+            access: self.access.clone(),
+            metrics: self.metrics.clone(),
+            shutdown: self.shutdown.clone(),
+            cors: self.cors.clone().map(Arc::new),
+            cache_max_age: self.cache_max_age,
+            required_sources: Arc::new(self.required_sources.clone()),
+            unix_socket: false,
+        }
+    }
+
+    // Tls only applies to tcp listeners, every connection uses the latest certificate.
+    async fn accept_connections(
+        &self,
+        listener: BoundListener,
+        acceptor: Option<watch::Receiver<TlsAcceptor>>,
+        active: mpsc::Sender<()>,
+    ) {
+        loop {
+            let accepted =
+                match future::select(Box::pin(listener.accept()), Box::pin(self.shutdown.wait()))
+                    .await
+                {
+                    Either::Left((accepted, _)) => accepted,
+                    Either::Right(_) => break,
+                };
+            let conn = match accepted {
+                Ok(conn) => conn,
+                Err(e) => {
+                    // Errors like running out of file descriptors persist for a while,
+                    // back off instead of spinning on them.
+                    logging::warn("failed to accept connection", &[("error", &e)]);
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                    continue;
+                }
             };
-            let addr = conn.remote_addr();
-            let service = service_fn(move |req| handle_lookup(context.clone(), addr, req));
-            async move { Ok::<_, Infallible>(service) }
-        });
 
-        let server = Server::bind(&addr).serve(make_service);
-        println!("Running Lookup Service at {}", addr);
-        if let Err(e) = server.await {
-            eprintln!("server error: {}", e);
+            let context = self.create_context();
+            let active = active.clone();
+            let acceptor = acceptor.as_ref().map(|acceptor| acceptor.borrow().clone());
+            match (conn, acceptor) {
+                (Connection::Tcp(stream, remote_addr), Some(acceptor)) => {
+                    tokio::spawn(async move {
+                        let _active = active;
+                        match acceptor.accept(stream).await {
+                            Ok(stream) => serve_connection(context, stream, remote_addr).await,
+                            Err(e) => logging::debug(
+                                "tls handshake failed",
+                                &[("remote_addr", &remote_addr), ("error", &e)],
+                            ),
+                        }
+                    });
+                }
+                (Connection::Tcp(stream, remote_addr), None) => {
+                    tokio::spawn(async move {
+                        let _active = active;
+                        serve_connection(context, stream, remote_addr).await
+                    });
+                }
+                (Connection::Unix(stream), _) => {
+                    let context = LookupContext {
+                        unix_socket: true,
+                        ..context
+                    };
+                    tokio::spawn(async move {
+                        let _active = active;
+                        serve_connection(context, stream, listener::unix_peer_addr()).await
+                    });
+                }
+            }
+        }
+    }
+}
+
+async fn serve_connection<S>(context: LookupContext, stream: S, remote_addr: SocketAddr)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let shutdown = context.shutdown.clone();
+    let service = service_fn(move |req| handle_request(context.clone(), remote_addr, req));
+    let mut conn = Box::pin(Http::new().serve_connection(stream, service));
+    let result = match future::select(conn.as_mut(), Box::pin(shutdown.wait())).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => {
+            conn.as_mut().graceful_shutdown();
+            conn.await
+        }
+    };
+    if let Err(e) = result {
+        logging::debug(
+            "connection error",
+            &[("remote_addr", &remote_addr), ("error", &e)],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::status;
+
+    fn disabled(name: &str) -> DisabledDataSource {
+        status::create_disabled_data_source(name, Path::new("/missing"), "not found".to_owned())
+    }
+
+    #[test]
+    fn health_routes() {
+        assert_eq!(
+            Route::from_path("/health", false, false),
+            Some(Route::Health)
+        );
+        assert_eq!(
+            Route::from_path("/health/live", false, false),
+            Some(Route::Health)
+        );
+        assert_eq!(
+            Route::from_path("/health/ready", false, false),
+            Some(Route::Ready)
+        );
+    }
+
+    #[test]
+    fn ready_with_missing_optional_sources() {
+        let (status, body) = readiness(
+            false,
+            &[disabled("maxmind"), disabled("public-suffix-list")],
+            &[],
+        );
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ready");
+    }
+
+    #[test]
+    fn not_ready_without_a_required_source() {
+        let required = vec!["ip2asn".to_owned()];
+        let (status, body) =
+            readiness(false, &[disabled("maxmind"), disabled("ip2asn")], &required);
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "degraded");
+        assert_eq!(body["disabled"], serde_json::json!(["ip2asn"]));
+
+        let (status, _) = readiness(false, &[disabled("maxmind")], &required);
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    fn languages(uri: &str, accept_language: Option<&str>) -> Vec<String> {
+        let mut req = Request::builder().uri(uri);
+        if let Some(value) = accept_language {
+            req = req.header("Accept-Language", value);
         }
+        get_projection(&req.body(Body::empty()).unwrap())
+            .languages()
+            .to_vec()
+    }
+
+    #[test]
+    fn projection_languages() {
+        assert!(languages("/ip/1.1.1.1", None).is_empty());
+        assert_eq!(
+            languages("/ip/1.1.1.1", Some("de-DE,fr;q=0.5")),
+            ["de-DE", "fr"]
+        );
+        assert_eq!(languages("/ip/1.1.1.1?lang", Some("de")), ["de"]);
+        assert_eq!(languages("/ip/1.1.1.1?lang=ja", Some("de")), ["ja"]);
+        assert!(languages("/ip/1.1.1.1?geo=full", Some("de")).is_empty());
+    }
+
+    #[test]
+    fn not_ready_while_draining() {
+        let (status, body) = readiness(true, &[], &[]);
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "draining");
     }
 }
@@ -0,0 +1,76 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+
+use crate::logging;
+
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    draining: Arc<AtomicBool>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl ShutdownHandle {
+    // Set as soon as a shutdown is requested, readiness checks fail from then on.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    // Resolves once listeners should stop accepting and open connections should drain,
+    // which is the configured delay after the shutdown request.
+    pub async fn wait(&self) {
+        let mut receiver = self.receiver.clone();
+        while !*receiver.borrow() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+// A first SIGTERM or SIGINT starts a graceful shutdown, a second one exits immediately.
+pub fn listen_for_signals(delay: Duration) -> ShutdownHandle {
+    let (sender, receiver) = watch::channel(false);
+    let draining = Arc::new(AtomicBool::new(false));
+    let handle = ShutdownHandle {
+        draining: draining.clone(),
+        receiver,
+    };
+
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        draining.store(true, Ordering::SeqCst);
+        logging::info(
+            "shutdown requested, reporting not ready",
+            &[("delay_secs", &delay.as_secs())],
+        );
+        tokio::spawn(async {
+            wait_for_signal().await;
+            logging::warn("second shutdown signal, exiting immediately", &[]);
+            std::process::exit(1);
+        });
+        tokio::time::sleep(delay).await;
+        let _ = sender.send(true);
+    });
+    handle
+}
+
+async fn wait_for_signal() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            logging::warn("unable to listen for SIGTERM", &[("error", &e)]);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    future::select(
+        Box::pin(terminate.recv()),
+        Box::pin(tokio::signal::ctrl_c()),
+    )
+    .await;
+}
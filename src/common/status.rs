@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub fn create_data_file_info(name: &str, path: &Path) -> DataFileInfo {
+    let metadata = fs::metadata(path).ok();
+    DataFileInfo {
+        name: name.to_owned(),
+        path: path.to_path_buf(),
+        size: metadata.as_ref().map(|m| m.len()),
+        modified: metadata
+            .and_then(|m| m.modified().ok())
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs()),
+        records: None,
+        node_count: None,
+        database_type: None,
+        build_epoch: None,
+        rib_timestamp: None,
+    }
+}
+
+pub fn create_disabled_data_source(name: &str, path: &Path, reason: String) -> DisabledDataSource {
+    DisabledDataSource {
+        name: name.to_owned(),
+        path: path.to_path_buf(),
+        reason,
+    }
+}
+
+pub fn create_data_status(sources: &DataSources, max_age: Duration) -> DataStatus {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let files: Vec<DataFileStatus> = sources
+        .files
+        .iter()
+        .map(|info| {
+            let age = info.age_timestamp().map(|t| now.saturating_sub(t));
+            DataFileStatus {
+                info: info.clone(),
+                age_seconds: age,
+                stale: age.map(|a| a > max_age.as_secs()).unwrap_or(false),
+            }
+        })
+        .collect();
+
+    let warnings = files
+        .iter()
+        .filter(|f| f.stale)
+        .map(|f| {
+            format!(
+                "{} ({}) is {} days old, older than the maximum of {} days",
+                f.info.name,
+                f.info.path.display(),
+                f.age_seconds.unwrap_or(0) / 86400,
+                max_age.as_secs() / 86400
+            )
+        })
+        .collect();
+
+    DataStatus {
+        files,
+        disabled: sources.disabled.clone(),
+        warnings,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DataFileInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: Option<u64>,
+    pub modified: Option<u64>,
+    pub records: Option<usize>,
+    pub node_count: Option<u32>,
+    pub database_type: Option<String>,
+    pub build_epoch: Option<u64>,
+    pub rib_timestamp: Option<String>,
+}
+
+impl DataFileInfo {
+    // The data inside a file is only as fresh as its build, so prefer the embedded build
+    // time over the file's mtime when one is known.
+    fn age_timestamp(&self) -> Option<u64> {
+        self.build_epoch.or(self.modified)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DisabledDataSource {
+    pub name: String,
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DataSources {
+    pub files: Vec<DataFileInfo>,
+    pub disabled: Vec<DisabledDataSource>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DataFileStatus {
+    #[serde(flatten)]
+    pub info: DataFileInfo,
+    pub age_seconds: Option<u64>,
+    pub stale: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DataStatus {
+    pub files: Vec<DataFileStatus>,
+    pub disabled: Vec<DisabledDataSource>,
+    pub warnings: Vec<String>,
+}
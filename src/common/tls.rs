@@ -0,0 +1,104 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustls_pemfile::Item;
+use tokio::sync::watch;
+use tokio_rustls::rustls::server::AllowAnyAuthenticatedClient;
+use tokio_rustls::rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+use crate::logging;
+use crate::reload;
+
+#[derive(Debug, Clone)]
+pub struct TlsOptions {
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+    pub client_ca_file: Option<PathBuf>,
+}
+
+pub fn create_tls_acceptor(options: &TlsOptions) -> Result<TlsAcceptor, String> {
+    let certs = load_certificates(&options.cert_file)?;
+    let key = load_private_key(&options.key_file)?;
+
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match &options.client_ca_file {
+        Some(ca_file) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certificates(ca_file)? {
+                roots.add(&cert).map_err(|err| {
+                    format!("invalid ca certificate in {}: {:?}", ca_file.display(), err)
+                })?;
+            }
+            builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder
+        .with_single_cert(certs, key)
+        .map_err(|err| format!("invalid certificate or key: {}", err))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+// Certificates are reloaded on SIGHUP and used for every connection accepted afterwards, if
+// the new files fail to load the previous certificate keeps being served.
+pub fn watch_tls_acceptor(
+    options: &TlsOptions,
+    mut sighup: watch::Receiver<u64>,
+) -> Result<watch::Receiver<TlsAcceptor>, String> {
+    let (sender, receiver) = watch::channel(create_tls_acceptor(options)?);
+    let options = options.clone();
+    tokio::spawn(async move {
+        loop {
+            reload::next_sighup(&mut sighup).await;
+            match create_tls_acceptor(&options) {
+                Ok(acceptor) => {
+                    if sender.send(acceptor).is_err() {
+                        break;
+                    }
+                    logging::info("reloaded tls certificate", &[]);
+                }
+                Err(err) => logging::warn(
+                    "tls certificate reload failed, keeping current certificate",
+                    &[("error", &err)],
+                ),
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+fn read_pem_items(path: &Path) -> Result<Vec<Item>, String> {
+    let file = File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    rustls_pemfile::read_all(&mut BufReader::new(file))
+        .map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+fn load_certificates(path: &Path) -> Result<Vec<Certificate>, String> {
+    let certs: Vec<Certificate> = read_pem_items(path)?
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::X509Certificate(der) => Some(Certificate(der)),
+            _ => None,
+        })
+        .collect();
+    if certs.is_empty() {
+        Err(format!("{}: no certificates found", path.display()))
+    } else {
+        Ok(certs)
+    }
+}
+
+fn load_private_key(path: &Path) -> Result<PrivateKey, String> {
+    read_pem_items(path)?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(der) | Item::RSAKey(der) | Item::ECKey(der) => Some(PrivateKey(der)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("{}: no private key found", path.display()))
+}
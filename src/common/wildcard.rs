@@ -0,0 +1,198 @@
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeSet;
+use std::hash::{BuildHasher, Hasher};
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::future;
+
+use crate::dns::{DnsAddressLookupResult, DnsResolverHandle};
+use crate::psl::SuffixLookupResult;
+
+static PROBE_COUNT: usize = 2;
+
+// Probes go to the closest zone that is still registrable: the parent of a subdomain, or
+// the name itself when it is the registrable domain. Public suffixes are never probed.
+pub fn parent_zone(domain: &str, suffix: Option<&SuffixLookupResult>) -> Option<String> {
+    let domain = domain.trim_end_matches('.');
+    match suffix {
+        Some(suffix) => {
+            let registrable = suffix.registrable_domain.as_deref()?;
+            if domain.eq_ignore_ascii_case(registrable) {
+                Some(registrable.to_owned())
+            } else {
+                domain.find('.').map(|idx| domain[idx + 1..].to_owned())
+            }
+        }
+        None => domain
+            .find('.')
+            .map(|idx| domain[idx + 1..].to_owned())
+            .filter(|zone| zone.contains('.')),
+    }
+}
+
+pub async fn probe_zone(resolver: &DnsResolverHandle, zone: &str) -> WildcardProbe {
+    let zone = zone.trim_end_matches('.').to_owned();
+    let names: Vec<String> = (0..PROBE_COUNT)
+        .map(|_| format!("{}.{}", random_label(), zone))
+        .collect();
+    let answers = future::join_all(
+        names
+            .iter()
+            .map(|name| resolver.address_lookup(name.clone())),
+    )
+    .await;
+
+    WildcardProbe {
+        zone,
+        answers: answers
+            .into_iter()
+            .filter(|a| !a.is_empty())
+            .map(|a| AnswerSet::from(&a))
+            .collect(),
+    }
+}
+
+fn random_label() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    hasher.write_u128(nanos);
+    format!("nl-probe-{:016x}", hasher.finish())
+}
+
+pub struct WildcardProbe {
+    zone: String,
+    answers: Vec<AnswerSet>,
+}
+
+impl WildcardProbe {
+    pub fn is_wildcard(&self) -> bool {
+        !self.answers.is_empty()
+    }
+
+    pub fn matches(&self, answer: &DnsAddressLookupResult) -> bool {
+        if answer.is_empty() {
+            return false;
+        }
+        let answer = AnswerSet::from(answer);
+        self.answers.iter().any(|probe| probe.matches(&answer))
+    }
+
+    pub fn to_result(&self, answer: &DnsAddressLookupResult) -> WildcardLookupResult {
+        WildcardLookupResult {
+            zone: self.zone.clone(),
+            zone_has_wildcard: self.is_wildcard(),
+            matches_wildcard: self.matches(answer),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq)]
+struct AnswerSet {
+    addrs: BTreeSet<IpAddr>,
+    cnames: BTreeSet<String>,
+}
+
+impl AnswerSet {
+    fn from(answer: &DnsAddressLookupResult) -> AnswerSet {
+        let mut addrs = BTreeSet::new();
+        addrs.extend(answer.a.iter().map(|ip| IpAddr::from(*ip)));
+        addrs.extend(answer.aaaa.iter().map(|ip| IpAddr::from(*ip)));
+        AnswerSet {
+            addrs,
+            cnames: answer.cname.iter().cloned().collect(),
+        }
+    }
+
+    // Wildcard CNAMEs commonly point at load balanced targets whose addresses rotate
+    // between queries, so the CNAME set alone decides when one is present.
+    fn matches(&self, other: &AnswerSet) -> bool {
+        if !self.cnames.is_empty() || !other.cnames.is_empty() {
+            self.cnames == other.cnames
+        } else {
+            self.addrs == other.addrs
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct WildcardLookupResult {
+    pub zone: String,
+    pub zone_has_wildcard: bool,
+    pub matches_wildcard: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn suffix(registrable_domain: Option<&str>, public_suffix: &str) -> SuffixLookupResult {
+        SuffixLookupResult {
+            registrable_domain: registrable_domain.map(|d| d.to_owned()),
+            public_suffix: public_suffix.to_owned(),
+            is_private_suffix: false,
+        }
+    }
+
+    fn answer(a: &[[u8; 4]], cname: &[&str]) -> AnswerSet {
+        AnswerSet::from(&DnsAddressLookupResult {
+            a: a.iter().map(|ip| Ipv4Addr::from(*ip)).collect(),
+            aaaa: Vec::new(),
+            cname: cname.iter().map(|c| c.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn parent_zone_of_a_subdomain() {
+        let psl = suffix(Some("example.co.uk"), "co.uk");
+        assert_eq!(
+            parent_zone("www.example.co.uk.", Some(&psl)),
+            Some("example.co.uk".to_owned())
+        );
+        assert_eq!(
+            parent_zone("a.b.example.co.uk", Some(&psl)),
+            Some("b.example.co.uk".to_owned())
+        );
+    }
+
+    #[test]
+    fn parent_zone_stops_at_the_registrable_domain() {
+        let psl = suffix(Some("example.co.uk"), "co.uk");
+        assert_eq!(
+            parent_zone("example.co.uk", Some(&psl)),
+            Some("example.co.uk".to_owned())
+        );
+        assert_eq!(parent_zone("co.uk", Some(&suffix(None, "co.uk"))), None);
+    }
+
+    #[test]
+    fn parent_zone_without_a_suffix_list() {
+        assert_eq!(
+            parent_zone("www.example.com", None),
+            Some("example.com".to_owned())
+        );
+        assert_eq!(parent_zone("example.com", None), None);
+        assert_eq!(parent_zone("com", None), None);
+    }
+
+    #[test]
+    fn cname_sets_take_precedence() {
+        let probe = answer(&[[192, 0, 2, 1]], &["lb.example.net"]);
+        assert!(probe.matches(&answer(&[[192, 0, 2, 2]], &["lb.example.net"])));
+        assert!(!probe.matches(&answer(&[[192, 0, 2, 1]], &["other.example.net"])));
+        assert!(!probe.matches(&answer(&[[192, 0, 2, 1]], &[])));
+    }
+
+    #[test]
+    fn address_sets_must_be_equal() {
+        let probe = answer(&[[192, 0, 2, 1], [192, 0, 2, 2]], &[]);
+        assert!(probe.matches(&answer(&[[192, 0, 2, 2], [192, 0, 2, 1]], &[])));
+        assert!(!probe.matches(&answer(&[[192, 0, 2, 1]], &[])));
+        assert!(!probe.matches(&answer(&[[192, 0, 2, 3], [192, 0, 2, 2]], &[])));
+    }
+}
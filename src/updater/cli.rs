@@ -27,6 +27,21 @@ pub fn create_cli_app() -> App<'static, 'static> {
                 .long("exclude-maxmind")
                 .help("Exclude maxmind database from update process."),
         )
+        .arg(
+            Arg::with_name("exclude-maxmind-asn")
+                .long("exclude-maxmind-asn")
+                .help("Exclude maxmind asn database from update process."),
+        )
+        .arg(
+            Arg::with_name("exclude-maxmind-country")
+                .long("exclude-maxmind-country")
+                .help("Exclude maxmind country database from update process."),
+        )
+        .arg(
+            Arg::with_name("exclude-psl")
+                .long("exclude-psl")
+                .help("Exclude public suffix list from update process."),
+        )
         .arg(
             Arg::with_name("maxmind-key")
                 .long("maxmind-key")
@@ -11,6 +11,9 @@ pub fn load_config() -> UpdaterConfig {
         exclude_asn: matches.is_present("exclude-asn"),
         exclude_ip2asn: matches.is_present("exclude-ip2asn"),
         exclude_maxmind: matches.is_present("exclude-maxmind"),
+        exclude_maxmind_asn: matches.is_present("exclude-maxmind-asn"),
+        exclude_maxmind_country: matches.is_present("exclude-maxmind-country"),
+        exclude_psl: matches.is_present("exclude-psl"),
         maxmind_key: get_string_value(&matches, "maxmind-key"),
         skip_optimize: matches.is_present("skip-optimize"),
     }
@@ -26,6 +29,9 @@ pub struct UpdaterConfig {
     pub exclude_asn: bool,
     pub exclude_ip2asn: bool,
     pub exclude_maxmind: bool,
+    pub exclude_maxmind_asn: bool,
+    pub exclude_maxmind_country: bool,
+    pub exclude_psl: bool,
     pub maxmind_key: Option<String>,
     pub skip_optimize: bool,
 }
@@ -21,6 +21,9 @@ use tempdir::TempDir;
 use common::files;
 use config::UpdaterConfig;
 
+static PSL_ICANN_BEGIN: &str = "// ===BEGIN ICANN DOMAINS===";
+static PSL_ICANN_END: &str = "// ===END ICANN DOMAINS===";
+
 fn main() {
     let conf = config::load_config();
     let target_dir_path = create_target_path(&conf);
@@ -29,6 +32,9 @@ fn main() {
     let asn_target_file = files::get_asn_path(&target_dir_path);
     let ip2asn_target_file = files::get_ip2asn_path(&target_dir_path);
     let maxmind_target_file = files::get_maxmind_path(&target_dir_path);
+    let maxmind_asn_target_file = files::get_maxmind_asn_path(&target_dir_path);
+    let maxmind_country_target_file = files::get_maxmind_country_path(&target_dir_path);
+    let psl_target_file = files::get_public_suffix_list_path(&target_dir_path);
 
     env::set_current_dir(working_dir.path()).unwrap();
 
@@ -50,11 +56,37 @@ fn main() {
     }
 
     if !conf.exclude_maxmind && conf.maxmind_key.is_some() {
+        let key = conf.maxmind_key.clone().unwrap();
         println!("Downloading maxmind city database...");
-        download_maxmind_city_database(&maxmind_target_file, conf.maxmind_key.unwrap());
+        download_maxmind_database("GeoLite2-City", &maxmind_target_file, &key);
+
+        if !conf.exclude_maxmind_asn {
+            println!("Downloading maxmind asn database...");
+            download_maxmind_database("GeoLite2-ASN", &maxmind_asn_target_file, &key);
+        }
+
+        if !conf.exclude_maxmind_country {
+            println!("Downloading maxmind country database...");
+            download_maxmind_database("GeoLite2-Country", &maxmind_country_target_file, &key);
+        }
+    }
+
+    let mut failed = false;
+    if !conf.exclude_psl {
+        println!("Downloading public suffix list...");
+        if let Err(err) = download_public_suffix_list(&psl_target_file) {
+            println!(
+                "ERROR: Unable to update the public suffix list, keeping the current one: {}",
+                err
+            );
+            failed = true;
+        }
     }
 
     working_dir.close().unwrap();
+    if failed {
+        std::process::exit(1);
+    }
 }
 
 fn create_target_path(conf: &UpdaterConfig) -> PathBuf {
@@ -107,14 +139,49 @@ fn create_ip2asn_file(target_file: &Path) {
         .unwrap();
 }
 
-fn download_maxmind_city_database(target_file: &Path, key: String) {
-    let maxmind_archive_path = Path::new("maxmind-geolite-city.tar.gz");
+// Downloads next to the target and renames it into place once it looks like a complete
+// list, so a failed or truncated download never replaces the list the daemon is using.
+fn download_public_suffix_list(target_file: &Path) -> Result<(), String> {
+    let download_file = target_file.with_extension("dat.download");
+    let result = fetch_public_suffix_list(&download_file)
+        .and_then(|_| fs::rename(&download_file, target_file).map_err(|err| err.to_string()));
+    if result.is_err() {
+        let _ = fs::remove_file(&download_file);
+    }
+    result
+}
+
+fn fetch_public_suffix_list(download_file: &Path) -> Result<(), String> {
+    let mut response =
+        reqwest::blocking::get("https://publicsuffix.org/list/public_suffix_list.dat")
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| err.to_string())?;
+    let mut download = File::create(download_file).map_err(|err| err.to_string())?;
+    response
+        .copy_to(&mut download)
+        .map_err(|err| err.to_string())?;
+
+    let list = fs::read_to_string(download_file).map_err(|err| err.to_string())?;
+    if list.contains(PSL_ICANN_BEGIN) && list.contains(PSL_ICANN_END) {
+        Ok(())
+    } else {
+        Err("the download is not a public suffix list".to_owned())
+    }
+}
+
+fn download_maxmind_database(edition: &str, target_file: &Path, key: &str) {
+    let archive_name = format!("maxmind-{}.tar.gz", edition.to_lowercase());
+    let maxmind_archive_path = Path::new(&archive_name);
     let mut maxmind_archive_file = File::create(&maxmind_archive_path).unwrap();
-    reqwest::blocking::get(&format!("https://download.maxmind.com/app/geoip_download?edition_id=GeoLite2-City&license_key={}&suffix=tar.gz", key)).unwrap().copy_to(&mut maxmind_archive_file).unwrap();
+    reqwest::blocking::get(&format!("https://download.maxmind.com/app/geoip_download?edition_id={}&license_key={}&suffix=tar.gz", edition, key)).unwrap().copy_to(&mut maxmind_archive_file).unwrap();
     Command::new("tar")
         .args(&["xzf", &maxmind_archive_path.to_string_lossy()])
         .output()
         .unwrap();
-    let geolite_dir = glob("GeoLite2-City_*").unwrap().next().unwrap().unwrap();
-    fs::copy(geolite_dir.join("GeoLite2-City.mmdb"), &target_file).unwrap();
+    let geolite_dir = glob(&format!("{}_*", edition))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    fs::copy(geolite_dir.join(format!("{}.mmdb", edition)), &target_file).unwrap();
 }